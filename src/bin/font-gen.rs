@@ -6,58 +6,193 @@
 //! reference bitmap. The similarity metric is pretty slow, hence the need for
 //! a lookup table and offline generation of that table.
 //!
-//! Feel free to change the font by changing the reference table! The bitmap
-//! is specified using binary in left to right, top to bottom order.
+//! Without arguments, or with the names of built-in fonts, this regenerates
+//! the built-in fonts in src/lib/fonts from the reference tables below. Feel
+//! free to change them! The bitmap is specified using binary in left to
+//! right, top to bottom order.
+//!
+//! With `--reference <FILE> <OUTPUT>`, a font is generated from a reference
+//! file instead, which can then be loaded at runtime using dot-txt's --font
+//! option. Each non-empty line of a reference file holds a character followed
+//! by its bitmap in the same binary notation, for example:
+//!
+//! ```text
+//! + 010_010_111_010_010
+//! - 000_000_111_000_000
+//! ```
 
 use std::io::Write;
 
 use dot_txt::canvas;
 
-fn main() {
+/// The reference table of the ascii font.
+const ASCII: &[(char, u16)] = &[
+    (' ', 0b000_000_000_000_000),
+    ('_', 0b000_000_000_000_111),
+    ('.', 0b000_000_000_111_000),
+    ('-', 0b000_000_111_000_000),
+    ('\'', 0b000_111_000_000_000),
+    ('`', 0b111_000_000_000_000),
+    ('|', 0b001_001_001_001_001),
+    ('|', 0b010_010_010_010_010),
+    ('|', 0b100_100_100_100_100),
+    ('+', 0b010_010_111_010_010),
+    ('.', 0b000_000_100_100_100),
+    ('.', 0b000_000_010_010_010),
+    ('.', 0b000_000_001_001_001),
+    ('\'', 0b100_100_100_000_000),
+    ('\'', 0b010_010_010_000_000),
+    ('\'', 0b001_001_001_000_000),
+    ('\\', 0b100_110_010_011_001),
+    ('/', 0b001_011_010_110_100),
+    ('[', 0b011_010_010_010_011),
+    (']', 0b110_010_010_010_110),
+    ('(', 0b001_010_010_010_001),
+    (')', 0b100_010_010_010_100),
+    ('{', 0b011_010_110_010_011),
+    ('}', 0b110_010_011_010_110),
+    ('<', 0b001_010_100_010_001),
+    ('>', 0b100_010_001_010_100),
+    ('.', 0b000_000_000_010_000),
+    (',', 0b000_000_000_010_100),
+    ('=', 0b000_111_000_111_000),
+    ('\'', 0b010_010_000_000_000),
+    ('"', 0b101_101_000_000_000),
+    ('`', 0b100_010_000_000_000),
+    ('+', 0b000_010_111_010_000),
+    ('#', 0b101_111_101_111_101),
+];
+
+/// Corners and junctions of lines through the middle of a character, in the
+/// order ┌┐└┘├┤┬┴.
+const JUNCTIONS: [u16; 8] = [
+    0b000_000_011_010_010,
+    0b000_000_110_010_010,
+    0b010_010_011_000_000,
+    0b010_010_110_000_000,
+    0b010_010_011_010_010,
+    0b010_010_110_010_010,
+    0b000_000_111_010_010,
+    0b010_010_111_000_000,
+];
+
+/// Returns the reference table of the ascii font, with the given characters
+/// replacing the ASCII ones for lines and adding corners and junctions. The
+/// junctions are given in the same order as JUNCTIONS.
+fn with_lines(horizontal: char, vertical: char, cross: char, junctions: &str) -> Vec<(char, u16)> {
+    let mut charset: Vec<(char, u16)> = ASCII
+        .iter()
+        .map(|&(c, bits)| {
+            let c = match c {
+                '-' => horizontal,
+                '|' => vertical,
+                '+' => cross,
+                c => c,
+            };
+            (c, bits)
+        })
+        .collect();
+    charset.extend(junctions.chars().zip(JUNCTIONS));
+    charset
+}
+
+/// Returns the reference table of the built-in font with the given name.
+fn builtin(name: &str) -> Option<Vec<(char, u16)>> {
+    match name {
+        "ascii" => Some(ASCII.to_vec()),
+        "ascii-corners" => Some(with_lines('-', '|', '+', "++++++++")),
+        "unicode-light" => Some(
+            with_lines('─', '│', '┼', "┌┐└┘├┤┬┴")
+                .into_iter()
+                .map(|(c, bits)| match c {
+                    '/' => ('╱', bits),
+                    '\\' => ('╲', bits),
+                    c => (c, bits),
+                })
+                .collect(),
+        ),
+        "unicode-heavy" => Some(with_lines('━', '┃', '╋', "┏┓┗┛┣┫┳┻")),
+        _ => None,
+    }
+}
+
+/// Parses a reference file, as described above.
+fn parse_reference(text: &str) -> Result<Vec<(char, u16)>, String> {
+    let mut charset = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let mut chars = line.chars();
+        let Some(c) = chars.next() else {
+            continue;
+        };
+        let bits: String = chars.filter(|c| *c != '_' && !c.is_whitespace()).collect();
+        if bits.len() != 15 {
+            return Err(format!(
+                "line {}: expected a character and 15 bits",
+                number + 1
+            ));
+        }
+        let bits = u16::from_str_radix(&bits, 2)
+            .map_err(|e| format!("line {}: failed to parse bits ('{bits}'): {e}", number + 1))?;
+        charset.push((c, bits));
+    }
+    if charset.is_empty() {
+        return Err("no reference characters".to_string());
+    }
+    Ok(charset)
+}
+
+/// Generates a font from a reference table and writes it to a file.
+fn generate(charset: &[(char, u16)], output: &str) {
+    let charset: Vec<(char, canvas::BitmapChar)> = charset
+        .iter()
+        .map(|&(c, bits)| (c, canvas::BitmapChar::from_bits(bits)))
+        .collect();
     eprintln!();
-    let f = canvas::BitmapFont::generate(
-        &[
-            (' ', canvas::BitmapChar::from_bits(0b000_000_000_000_000)),
-            ('_', canvas::BitmapChar::from_bits(0b000_000_000_000_111)),
-            ('.', canvas::BitmapChar::from_bits(0b000_000_000_111_000)),
-            ('-', canvas::BitmapChar::from_bits(0b000_000_111_000_000)),
-            ('\'', canvas::BitmapChar::from_bits(0b000_111_000_000_000)),
-            ('`', canvas::BitmapChar::from_bits(0b111_000_000_000_000)),
-            ('|', canvas::BitmapChar::from_bits(0b001_001_001_001_001)),
-            ('|', canvas::BitmapChar::from_bits(0b010_010_010_010_010)),
-            ('|', canvas::BitmapChar::from_bits(0b100_100_100_100_100)),
-            ('+', canvas::BitmapChar::from_bits(0b010_010_111_010_010)),
-            ('.', canvas::BitmapChar::from_bits(0b000_000_100_100_100)),
-            ('.', canvas::BitmapChar::from_bits(0b000_000_010_010_010)),
-            ('.', canvas::BitmapChar::from_bits(0b000_000_001_001_001)),
-            ('\'', canvas::BitmapChar::from_bits(0b100_100_100_000_000)),
-            ('\'', canvas::BitmapChar::from_bits(0b010_010_010_000_000)),
-            ('\'', canvas::BitmapChar::from_bits(0b001_001_001_000_000)),
-            ('\\', canvas::BitmapChar::from_bits(0b100_110_010_011_001)),
-            ('/', canvas::BitmapChar::from_bits(0b001_011_010_110_100)),
-            ('[', canvas::BitmapChar::from_bits(0b011_010_010_010_011)),
-            (']', canvas::BitmapChar::from_bits(0b110_010_010_010_110)),
-            ('(', canvas::BitmapChar::from_bits(0b001_010_010_010_001)),
-            (')', canvas::BitmapChar::from_bits(0b100_010_010_010_100)),
-            ('{', canvas::BitmapChar::from_bits(0b011_010_110_010_011)),
-            ('}', canvas::BitmapChar::from_bits(0b110_010_011_010_110)),
-            ('<', canvas::BitmapChar::from_bits(0b001_010_100_010_001)),
-            ('>', canvas::BitmapChar::from_bits(0b100_010_001_010_100)),
-            ('.', canvas::BitmapChar::from_bits(0b000_000_000_010_000)),
-            (',', canvas::BitmapChar::from_bits(0b000_000_000_010_100)),
-            ('=', canvas::BitmapChar::from_bits(0b000_111_000_111_000)),
-            ('\'', canvas::BitmapChar::from_bits(0b010_010_000_000_000)),
-            ('"', canvas::BitmapChar::from_bits(0b101_101_000_000_000)),
-            ('`', canvas::BitmapChar::from_bits(0b100_010_000_000_000)),
-            ('+', canvas::BitmapChar::from_bits(0b000_010_111_010_000)),
-            ('#', canvas::BitmapChar::from_bits(0b101_111_101_111_101)),
-        ],
-        |progress| {
-            eprintln!("\r\x1B[A\x1B[KGenerating... {:.01}%", progress * 100f32);
-        },
-    );
-    let mut file = std::fs::File::create("src/lib/font.txt").expect("failed to open output file");
+    let f = canvas::BitmapFont::generate(&charset, |progress| {
+        eprintln!(
+            "\r\x1B[A\x1B[KGenerating {output}... {:.01}%",
+            progress * 100f32
+        );
+    });
+    let mut file = std::fs::File::create(output).expect("failed to open output file");
     file.write_all(f.serialize().as_bytes())
         .expect("failed to write to output file");
-    eprintln!("\r\x1B[A\x1B[KGenerating... done");
+    eprintln!("\r\x1B[A\x1B[KGenerating {output}... done");
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--reference") {
+        let [_, reference, output] = args.as_slice() else {
+            eprintln!("usage: dot-txt-font-gen --reference <FILE> <OUTPUT>");
+            std::process::exit(1);
+        };
+        let charset = std::fs::read_to_string(reference)
+            .map_err(|e| format!("failed to read {reference}: {e}"))
+            .and_then(|text| parse_reference(&text).map_err(|e| format!("{reference}: {e}")));
+        match charset {
+            Ok(charset) => generate(&charset, output),
+            Err(e) => {
+                eprintln!("dot-txt-font-gen: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let names: Vec<&str> = if args.is_empty() {
+        canvas::BUILTIN_FONTS.to_vec()
+    } else {
+        args.iter().map(String::as_str).collect()
+    };
+    for name in names {
+        let Some(charset) = builtin(name) else {
+            eprintln!(
+                "dot-txt-font-gen: unknown font '{name}', expected one of {}",
+                canvas::BUILTIN_FONTS.join(", ")
+            );
+            std::process::exit(1);
+        };
+        generate(&charset, &format!("src/lib/fonts/{name}.txt"));
+    }
 }
@@ -1,41 +1,541 @@
+//! Renders a graph that was laid out by graphviz as ASCII art.
+//!
+//! The input is either a graph laid out by graphviz, in one of its plain,
+//! JSON or xdot output formats as produced by for example `dot -Tplain`,
+//! `dot -Tjson` or `dot -Txdot`, or DOT source code, which is then laid out
+//! by the built-in layout engine. Run with `--help` for usage information.
+
 use dot_txt::canvas;
+use dot_txt::color::ColorMode;
 use dot_txt::dot;
 use dot_txt::dot::Coord;
+use dot_txt::graph;
+use dot_txt::render;
+use dot_txt::snap;
 use std::fs::File;
-use std::io::BufReader;
-
-fn main() {
-    let mut file = BufReader::new(File::open("test.plain").expect("open failed"));
-    let graph = dot::Graph::from_plain(&mut file).expect("parse failed");
-    drop(file);
-
-    let mut c = canvas::Canvas::new(200.0, Coord::new(50.0, 50.0));
-    for (_, node) in graph.nodes.iter() {
-        c.draw_rect(node.coord - node.size / 2.0, node.coord + node.size / 2.0);
-    }
-    for edge in graph.edges.iter() {
-        let mut iter = edge.cpts.iter();
-        if let Some(mut a) = iter.next() {
-            for b in iter {
-                c.draw_line(*a, *b);
-                a = b;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::process::ExitCode;
+
+/// Usage information printed for --help and on usage errors.
+const USAGE: &str = "\
+Usage: dot-txt [OPTIONS] [INPUT]
+
+Renders a graph as ASCII art. The graph is either laid out by graphviz
+(dot -Tplain, -Tjson or -Txdot), or given as DOT source and laid out by
+dot-txt.
+
+Arguments:
+  [INPUT]  input file in graphviz plain, JSON or xdot format or in the
+           DOT language, or - for stdin [default: -]
+
+Options:
+  -f, --format <FMT>    input format: plain (also accepts plain-ext), json
+                        (also accepts json0), xdot (also accepts the output
+                        of dot -Tdot), dot to lay out the graph with
+                        dot-txt, or auto to detect the format from the
+                        start of the input, using the layout of DOT input
+                        that has one [default: auto]
+  -o, --output <FILE>   write the output to FILE instead of stdout
+  -w, --width <COLS>    scale the graph to span this many columns
+      --density <CPI>   scale the graph to this many columns per inch, with
+                        half as many rows per inch [default: 16]
+  -s, --scale <X[,Y]>   scale factor from inches to pixels, three per column
+                        and five per row, instead of deriving it from the
+                        width or density
+  -r, --routing <MODE>  edge routing: splines to follow the layout, or ortho
+                        to route edges using only horizontal and vertical
+                        lines [default: splines]
+  -c, --charset <SET>   characters for line art: ascii, light, rounded,
+                        heavy or double for Unicode box-drawing characters,
+                        or braille to show the pixels of each character as
+                        a braille pattern instead of the closest character,
+                        folding its 3x5 pixels into 2x4 dots
+                        [default: ascii]
+      --font <FONT>     bitmap font that picks the characters for line art,
+                        also used for the parts that --charset light,
+                        rounded, heavy or double has no box-drawing
+                        character for: ascii, ascii-corners to also use +
+                        for corners, unicode-light or unicode-heavy for
+                        box-drawing characters, or a font file generated by
+                        dot-txt-font-gen, which is assumed for values that
+                        contain a path separator or name an existing file;
+                        braille patterns do not use a font [default: ascii]
+      --color <WHEN>    color output using ANSI escape codes: auto to only do
+                        so when writing to a terminal and NO_COLOR is not
+                        set, always, never, or 16, 256 or truecolor to force
+                        a specific number of colors [default: auto]
+      --snap            snap nodes to the character grid and move them apart
+                        where they would overlap or touch after rounding
+  -t, --trim <PADDING>  remove empty rows and columns around the output,
+                        leaving PADDING of them on each side
+      --shade <SHADE>   shading of filled shapes: auto to pick one based on
+                        the fill color, light, medium, dark, or none to only
+                        use a background color [default: auto]
+  -d, --debug           render the pixel buffer directly instead of using the
+                        bitmap font
+  -h, --help            print this help and exit
+";
+
+/// Exit code for errors while reading, parsing or writing.
+const EXIT_FAILURE: u8 = 1;
+
+/// Exit code for invalid command-line usage.
+const EXIT_USAGE: u8 = 2;
+
+/// Input file formats.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// Detect the format from the first non-whitespace character.
+    Auto,
+    /// graphviz' plain or plain-ext format, loaded with Graph::from_plain().
+    Plain,
+    /// graphviz' json or json0 format, loaded with Graph::from_json().
+    Json,
+    /// graphviz' xdot or dot format, loaded with Graph::from_xdot().
+    Xdot,
+    /// DOT source, parsed and laid out using dot-txt's own layout engine.
+    Dot,
+}
+
+impl Format {
+    /// Detects the format from the start of the input. JSON output always
+    /// starts with a brace, whereas the plain format always starts with a
+    /// graph statement followed by the scale factor. Anything else is assumed
+    /// to be DOT source, which may turn out to be xdot once it is parsed.
+    fn detect(buffer: &[u8]) -> Format {
+        let text = String::from_utf8_lossy(buffer);
+        let text = text.trim_start();
+        if text.starts_with('{') {
+            return Format::Json;
+        }
+        let mut words = text.split_whitespace();
+        if words.next() == Some("graph") && words.next().is_some_and(|w| w.parse::<f64>().is_ok()) {
+            return Format::Plain;
+        }
+        Format::Dot
+    }
+}
+
+/// When to color the output.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ColorChoice {
+    /// Color the output if it is a terminal, unless NO_COLOR is set.
+    Auto,
+    /// Always color the output, detecting the number of colors the terminal
+    /// supports.
+    Always,
+    /// Never color the output.
+    Never,
+    /// Always color the output, using the given kind of escape codes.
+    Mode(ColorMode),
+}
+
+impl ColorChoice {
+    /// Detects the number of colors the terminal supports from the TERM and
+    /// COLORTERM environment variables.
+    fn detect_mode() -> ColorMode {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorMode::TrueColor
+        } else if term.contains("256color") {
+            ColorMode::Ansi256
+        } else {
+            ColorMode::Ansi16
+        }
+    }
+
+    /// Resolves the choice to the kind of escape codes to use, if any, given
+    /// whether the output goes to a terminal.
+    fn resolve(self, terminal: bool) -> Option<ColorMode> {
+        match self {
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                if terminal && !no_color {
+                    Some(ColorChoice::detect_mode())
+                } else {
+                    None
+                }
             }
+            ColorChoice::Always => Some(ColorChoice::detect_mode()),
+            ColorChoice::Never => None,
+            ColorChoice::Mode(mode) => Some(mode),
         }
     }
+}
+
+/// Command-line options.
+struct Options {
+    /// Input file, or None for stdin.
+    input: Option<String>,
+
+    /// Input file format.
+    format: Format,
+
+    /// Output file, or None for stdout.
+    output: Option<String>,
 
-    /*c.draw_string(&Coord { x: 100.0, y: 100.0 }, "hello");
-    c.draw_rect(
-        &Coord { x: 50.0, y: 50.0 },
-        &Coord { x: 150.0, y: 150.0 },
-    );
-    c.draw_line(
-        &Coord { x: 50.0, y: 50.0 },
-        &Coord { x: 150.0, y: 150.0 },
-    );
-    c.draw_line(
-        &Coord { x: 50.0, y: 110.0 },
-        &Coord { x: 150.0, y: 90.0 },
-    );*/
-    println!("{c}");
-    println!("{c:#}");
+    /// How to size the canvas, passed to Canvas::for_graph().
+    sizing: canvas::Sizing,
+
+    /// Canvas scale factor, passed to Canvas::new() instead of sizing the
+    /// canvas with Canvas::for_graph(), if given.
+    scale: Option<Coord>,
+
+    /// Options for render::draw_graph().
+    render: render::Options,
+
+    /// Character set for line art.
+    charset: canvas::Charset,
+
+    /// Name of a built-in bitmap font or path to a font file, or None for
+    /// the default font.
+    font: Option<String>,
+
+    /// When to color the output.
+    color: ColorChoice,
+
+    /// Padding to trim the canvas to, or None to not trim it.
+    trim: Option<usize>,
+
+    /// Whether to snap the graph to the character grid before drawing it.
+    snap: bool,
+
+    /// Whether to use Canvas::debug_render() instead of the bitmap font.
+    debug: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            input: None,
+            format: Format::Auto,
+            output: None,
+            sizing: canvas::Sizing::default(),
+            scale: None,
+            render: render::Options::default(),
+            charset: canvas::Charset::Ascii,
+            font: None,
+            color: ColorChoice::Auto,
+            trim: None,
+            snap: false,
+            debug: false,
+        }
+    }
+}
+
+impl Options {
+    /// Parses a floating-point option value, throwing a reasonable error
+    /// message on failure.
+    fn parse_float(option: &str, value: &str) -> Result<f64, String> {
+        match value.parse::<f64>() {
+            Ok(x) if x.is_finite() && x > 0.0 => Ok(x),
+            Ok(_) => Err(format!("value for {option} must be positive ('{value}')")),
+            Err(e) => Err(format!(
+                "failed to parse value for {option} ('{value}'): {e}"
+            )),
+        }
+    }
+
+    /// Parses a column count option value, throwing a reasonable error
+    /// message on failure.
+    fn parse_columns(option: &str, value: &str) -> Result<usize, String> {
+        match value.parse::<usize>() {
+            Ok(0) => Err(format!("value for {option} must be positive ('{value}')")),
+            Ok(x) => Ok(x),
+            Err(e) => Err(format!(
+                "failed to parse value for {option} ('{value}'): {e}"
+            )),
+        }
+    }
+
+    /// Parses a padding option value, throwing a reasonable error message on
+    /// failure.
+    fn parse_padding(option: &str, value: &str) -> Result<usize, String> {
+        value
+            .parse::<usize>()
+            .map_err(|e| format!("failed to parse value for {option} ('{value}'): {e}"))
+    }
+
+    /// Parses a scale option value, being either a single number for both
+    /// axes or a comma-separated X,Y pair.
+    fn parse_scale(option: &str, value: &str) -> Result<Coord, String> {
+        if let Some((x, y)) = value.split_once(',') {
+            Ok(Coord::new(
+                Options::parse_float(option, x)?,
+                Options::parse_float(option, y)?,
+            ))
+        } else {
+            let scale = Options::parse_float(option, value)?;
+            Ok(Coord::new(scale, scale))
+        }
+    }
+
+    /// Parses an input format option value.
+    fn parse_format(option: &str, value: &str) -> Result<Format, String> {
+        match value {
+            "auto" => Ok(Format::Auto),
+            "plain" | "plain-ext" => Ok(Format::Plain),
+            "json" | "json0" => Ok(Format::Json),
+            "xdot" | "xdot1.2" | "xdot1.4" => Ok(Format::Xdot),
+            "dot" | "gv" => Ok(Format::Dot),
+            _ => Err(format!("unknown value for {option} ('{value}')")),
+        }
+    }
+
+    /// Parses an edge routing option value.
+    fn parse_routing(option: &str, value: &str) -> Result<render::EdgeRouting, String> {
+        match value {
+            "splines" => Ok(render::EdgeRouting::Splines),
+            "ortho" | "orthogonal" => Ok(render::EdgeRouting::Orthogonal),
+            _ => Err(format!("unknown value for {option} ('{value}')")),
+        }
+    }
+
+    /// Parses a character set option value.
+    fn parse_charset(option: &str, value: &str) -> Result<canvas::Charset, String> {
+        match value {
+            "ascii" => Ok(canvas::Charset::Ascii),
+            "light" => Ok(canvas::Charset::BoxDrawing(canvas::BoxStyle::Light)),
+            "rounded" => Ok(canvas::Charset::BoxDrawing(canvas::BoxStyle::Rounded)),
+            "heavy" => Ok(canvas::Charset::BoxDrawing(canvas::BoxStyle::Heavy)),
+            "double" => Ok(canvas::Charset::BoxDrawing(canvas::BoxStyle::Double)),
+            "braille" => Ok(canvas::Charset::Braille),
+            _ => Err(format!("unknown value for {option} ('{value}')")),
+        }
+    }
+
+    /// Parses a color option value.
+    fn parse_color(option: &str, value: &str) -> Result<ColorChoice, String> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            "16" => Ok(ColorChoice::Mode(ColorMode::Ansi16)),
+            "256" => Ok(ColorChoice::Mode(ColorMode::Ansi256)),
+            "truecolor" | "24bit" => Ok(ColorChoice::Mode(ColorMode::TrueColor)),
+            _ => Err(format!("unknown value for {option} ('{value}')")),
+        }
+    }
+
+    /// Parses a shade option value.
+    fn parse_shade(option: &str, value: &str) -> Result<render::FillShade, String> {
+        match value {
+            "auto" => Ok(render::FillShade::Auto),
+            "light" => Ok(render::FillShade::Fixed(canvas::Shade::Light)),
+            "medium" => Ok(render::FillShade::Fixed(canvas::Shade::Medium)),
+            "dark" => Ok(render::FillShade::Fixed(canvas::Shade::Dark)),
+            "none" => Ok(render::FillShade::None),
+            _ => Err(format!("unknown value for {option} ('{value}')")),
+        }
+    }
+
+    /// Parses the command-line arguments, not including the program name.
+    /// Returns Ok(None) if the program should exit successfully without doing
+    /// anything else, i.e. for --help.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+        let mut options = Options::default();
+        let mut positional = vec![];
+        let mut only_positional = false;
+        while let Some(arg) = args.next() {
+            if only_positional || arg == "-" || !arg.starts_with('-') {
+                positional.push(arg);
+                continue;
+            }
+            if arg == "--" {
+                only_positional = true;
+                continue;
+            }
+
+            // Split --option=value syntax.
+            let (option, mut inline) = match arg.split_once('=') {
+                Some((option, value)) if arg.starts_with("--") => {
+                    (option.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = |args: &mut I| {
+                inline
+                    .take()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {option}"))
+            };
+
+            match &option[..] {
+                "-f" | "--format" => {
+                    options.format = Options::parse_format(&option, &value(&mut args)?)?;
+                }
+                "-o" | "--output" => {
+                    let file = value(&mut args)?;
+                    options.output = if file == "-" { None } else { Some(file) };
+                }
+                "-w" | "--width" => {
+                    options.sizing = canvas::Sizing::Columns(Options::parse_columns(
+                        &option,
+                        &value(&mut args)?,
+                    )?);
+                }
+                "--density" => {
+                    options.sizing =
+                        canvas::Sizing::Density(Options::parse_float(&option, &value(&mut args)?)?);
+                }
+                "-s" | "--scale" => {
+                    options.scale = Some(Options::parse_scale(&option, &value(&mut args)?)?);
+                }
+                "-r" | "--routing" => {
+                    options.render.edge_routing =
+                        Options::parse_routing(&option, &value(&mut args)?)?;
+                }
+                "-c" | "--charset" => {
+                    options.charset = Options::parse_charset(&option, &value(&mut args)?)?;
+                }
+                "--font" => {
+                    options.font = Some(value(&mut args)?);
+                }
+                "--color" => {
+                    options.color = Options::parse_color(&option, &value(&mut args)?)?;
+                }
+                "--shade" => {
+                    options.render.fill_shade = Options::parse_shade(&option, &value(&mut args)?)?;
+                }
+                "-t" | "--trim" => {
+                    options.trim = Some(Options::parse_padding(&option, &value(&mut args)?)?);
+                }
+                "--snap" => options.snap = true,
+                "-d" | "--debug" => options.debug = true,
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(format!("unrecognized option {option}")),
+            }
+        }
+
+        if positional.len() > 1 {
+            return Err("expected at most one input file".to_string());
+        }
+        options.input = positional.pop().filter(|file| file != "-");
+        if options.font.is_some() && options.charset == canvas::Charset::Braille {
+            return Err("--font has no effect with --charset braille".to_string());
+        }
+
+        Ok(Some(options))
+    }
+}
+
+/// Reads the input graph, renders it, and writes the result to the output.
+fn run(options: &Options) -> Result<(), String> {
+    // Load the font first, such that a missing font file is reported before
+    // doing any work. Anything that looks like a path or names an existing
+    // file is loaded from disk, such that a font file named like a built-in
+    // font can still be used.
+    let font = match &options.font {
+        Some(font)
+            if font.contains(std::path::is_separator) || std::path::Path::new(font).exists() =>
+        {
+            canvas::BitmapFont::load(font)?
+        }
+        Some(font) => canvas::BitmapFont::builtin(font)
+            .ok_or_else(|| format!("unknown value for --font ('{font}')"))?,
+        None => canvas::BitmapFont::default(),
+    };
+    let mut input: Box<dyn BufRead> = if let Some(file) = &options.input {
+        Box::new(BufReader::new(
+            File::open(file).map_err(|e| format!("failed to open {file}: {e}"))?,
+        ))
+    } else {
+        Box::new(std::io::stdin().lock())
+    };
+    let format = match options.format {
+        Format::Auto => Format::detect(
+            input
+                .fill_buf()
+                .map_err(|e| format!("failed to read input: {e}"))?,
+        ),
+        format => format,
+    };
+    let mut graph = match format {
+        Format::Json => dot::Graph::from_json(&mut input),
+        Format::Xdot => dot::Graph::from_xdot(&mut input),
+        // DOT source that graphviz already laid out has a bounding box.
+        Format::Dot if options.format == Format::Auto => graph::Graph::from_dot(&mut input)
+            .and_then(|graph| {
+                if graph.attributes.contains_key("bb") {
+                    dot::Graph::from_dot_layout(&graph)
+                } else {
+                    Ok(graph.layout())
+                }
+            }),
+        Format::Dot => graph::Graph::from_dot(&mut input).map(|graph| graph.layout()),
+        _ => dot::Graph::from_plain(&mut input),
+    }
+    .map_err(|e| format!("failed to parse input: {e}"))?;
+
+    let mut c = match options.scale {
+        Some(scale) => {
+            let mut c = canvas::Canvas::new(graph.width * scale.x, scale);
+            c.set_height(graph.height * scale.y);
+            c
+        }
+        None => canvas::Canvas::for_graph(&graph, options.sizing),
+    };
+    if options.snap {
+        // Snapping may grow the graph, so the canvas is recreated at the
+        // same scale to fit it.
+        snap::snap_to_grid(&mut graph, c.char_size());
+        let scale = c.scale();
+        c = canvas::Canvas::new(graph.width * scale.x, scale);
+        c.set_height(graph.height * scale.y);
+    }
+    render::draw_graph(&mut c, &graph, &options.render);
+    if let Some(padding) = options.trim {
+        c.trim(padding);
+    }
+    let terminal = options.output.is_none() && std::io::stdout().is_terminal();
+    let render_options = canvas::RenderOptions {
+        charset: options.charset,
+        color_mode: options.color.resolve(terminal),
+    };
+    let write = |output: &mut dyn Write| {
+        if options.debug {
+            write!(output, "{c:#}")?;
+        } else {
+            c.write_to(&mut *output, &font, &render_options)?;
+        }
+        output.flush()
+    };
+
+    if let Some(file) = &options.output {
+        let mut output = BufWriter::new(
+            File::create(file).map_err(|e| format!("failed to create {file}: {e}"))?,
+        );
+        write(&mut output).map_err(|e| format!("failed to write {file}: {e}"))
+    } else {
+        // Output piped into something like head may be cut short, which is
+        // not an error.
+        match write(&mut BufWriter::new(std::io::stdout().lock())) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+                Err(format!("failed to write output: {e}"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("dot-txt: {e}");
+            eprintln!();
+            eprint!("{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("dot-txt: {e}");
+        return ExitCode::from(EXIT_FAILURE);
+    }
+    ExitCode::SUCCESS
 }
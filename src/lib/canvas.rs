@@ -1,3 +1,16 @@
+use crate::color::{ColorMode, Rgb};
+use crate::dot::Graph;
+use crate::xdot::Align;
+
+/// The default number of columns per inch used by Canvas::for_graph(). This
+/// is about what it takes for labels in graphviz' default font to fit their
+/// nodes.
+pub const COLUMNS_PER_INCH: f64 = 16.0;
+
+/// The height of a character cell relative to its width, as in most
+/// terminal fonts.
+pub const CELL_ASPECT: f64 = 2.0;
+
 /// Represents a 3x5 pixel bitmap for a character position. The best option
 /// will be chosen. In LSB to MSB-1 order, the pixels are ordered
 /// left-to-right, top-to-bottom.
@@ -68,6 +81,10 @@ impl BitmapChar {
     }
 }
 
+/// The names of the built-in fonts, as accepted by BitmapFont::builtin(). The
+/// first one is the default font.
+pub const BUILTIN_FONTS: [&str; 4] = ["ascii", "ascii-corners", "unicode-light", "unicode-heavy"];
+
 /// A 3x5 pixel to character lookup table for box drawing.
 pub struct BitmapFont {
     data: [char; 32768],
@@ -99,16 +116,63 @@ impl BitmapFont {
         font
     }
 
-    /// Deserializes a font from a 32k-character string.
+    /// Deserializes a font from a 32k-character string. Panics if the string
+    /// is not a valid font; see try_deserialize() for untrusted data.
     pub fn deserialize(data: &str) -> BitmapFont {
-        assert!(data.len() == 32768);
+        match BitmapFont::try_deserialize(data) {
+            Ok(font) => font,
+            Err(e) => panic!("invalid font: {e}"),
+        }
+    }
+
+    /// Deserializes a font from a 32k-character string, as produced by
+    /// serialize(), or returns an error if it is not a valid font. A single
+    /// trailing newline is ignored, as text editors tend to add one.
+    pub fn try_deserialize(data: &str) -> Result<BitmapFont, String> {
+        let data = data.strip_suffix('\n').unwrap_or(data);
+        let count = data.chars().count();
+        if count != 32768 {
+            return Err(format!("expected 32768 characters, found {count}"));
+        }
         let mut font = BitmapFont {
             data: ['\0'; 32768],
         };
         for (i, c) in data.chars().enumerate() {
+            if c.is_control() {
+                return Err(format!("control character at index {i}"));
+            }
             font.data[i] = c
         }
-        font
+        Ok(font)
+    }
+
+    /// Returns the built-in font with the given name, if any. See
+    /// BUILTIN_FONTS for the available names:
+    ///
+    ///  - ascii draws lines using characters like `-|/\.'_` and `+` where
+    ///    lines cross.
+    ///  - ascii-corners is the same, but also uses `+` for corners and
+    ///    junctions.
+    ///  - unicode-light uses light box-drawing characters like `─│┌┼╱`.
+    ///  - unicode-heavy uses heavy box-drawing characters like `━┃┏╋`.
+    pub fn builtin(name: &str) -> Option<BitmapFont> {
+        let data = match name {
+            "ascii" => include_str!("fonts/ascii.txt"),
+            "ascii-corners" => include_str!("fonts/ascii-corners.txt"),
+            "unicode-light" => include_str!("fonts/unicode-light.txt"),
+            "unicode-heavy" => include_str!("fonts/unicode-heavy.txt"),
+            _ => return None,
+        };
+        Some(BitmapFont::deserialize(data))
+    }
+
+    /// Loads a font from a file, as written by the font generator.
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<BitmapFont, String> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read font {}: {e}", path.display()))?;
+        BitmapFont::try_deserialize(&data)
+            .map_err(|e| format!("failed to load font {}: {e}", path.display()))
     }
 
     /// Serializes a font into a 32k-character string.
@@ -124,10 +188,123 @@ impl BitmapFont {
 
 impl Default for BitmapFont {
     fn default() -> Self {
-        BitmapFont::deserialize(include_str!("font.txt"))
+        BitmapFont::builtin(BUILTIN_FONTS[0]).unwrap()
+    }
+}
+
+/// Style of the box-drawing characters used by Charset::BoxDrawing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoxStyle {
+    /// Light lines with square corners, like ┌─┐.
+    #[default]
+    Light,
+
+    /// Light lines with rounded corners, like ╭─╮.
+    Rounded,
+
+    /// Heavy lines, like ┏━┓.
+    Heavy,
+
+    /// Double lines, like ╔═╗.
+    Double,
+}
+
+/// The characters that Canvas::render_to() draws line art with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Charset {
+    /// ASCII characters, as chosen by the bitmap font.
+    #[default]
+    Ascii,
+
+    /// Unicode box-drawing characters of the given style wherever the line
+    /// art consists of horizontal and vertical lines, with proper junctions
+    /// where lines meet. Everything else, such as diagonal lines and curves,
+    /// is drawn with the bitmap font.
+    BoxDrawing(BoxStyle),
+
+    /// Unicode braille patterns, which represent each character as a grid
+    /// of 2x4 dots. This draws curves and diagonal lines more smoothly than
+    /// the bitmap font, but stroke styles other than dashed lines are not
+    /// distinguished.
+    Braille,
+}
+
+/// Options for Canvas::render_to().
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// The characters to draw line art with.
+    pub charset: Charset,
+
+    /// The kind of ANSI escape codes to render colors with, or None to
+    /// render without colors.
+    pub color_mode: Option<ColorMode>,
+}
+
+impl BoxStyle {
+    /// Returns the box-drawing character with the given arms, in the order
+    /// up, down, left, right, for a character of the given style. A single
+    /// arm yields a full straight line. Bold lines use heavy characters
+    /// unless the style is double, dashed and dotted lines use the dashed
+    /// and dotted characters for straight lines, and rounded corners are
+    /// used where requested if the style has light lines.
+    fn glyph(self, arms: [bool; 4], cell: CellStyle) -> Option<char> {
+        let heavy =
+            self == BoxStyle::Heavy || (cell.stroke == Stroke::Bold && self != BoxStyle::Double);
+        let style = match self {
+            _ if heavy => BoxStyle::Heavy,
+            BoxStyle::Light if cell.rounded => BoxStyle::Rounded,
+            style => style,
+        };
+        let glyphs = match style {
+            BoxStyle::Light => "│─┌┐└┘├┤┬┴┼",
+            BoxStyle::Rounded => "│─╭╮╰╯├┤┬┴┼",
+            BoxStyle::Heavy => "┃━┏┓┗┛┣┫┳┻╋",
+            BoxStyle::Double => "║═╔╗╚╝╠╣╦╩╬",
+        };
+        let index = match arms {
+            [true, true, false, false]
+            | [true, false, false, false]
+            | [false, true, false, false] => 0,
+            [false, false, true, true]
+            | [false, false, true, false]
+            | [false, false, false, true] => 1,
+            [false, true, false, true] => 2,
+            [false, true, true, false] => 3,
+            [true, false, false, true] => 4,
+            [true, false, true, false] => 5,
+            [true, true, false, true] => 6,
+            [true, true, true, false] => 7,
+            [false, true, true, true] => 8,
+            [true, false, true, true] => 9,
+            [true, true, true, true] => 10,
+            [false, false, false, false] => return None,
+        };
+        let broken = match (cell.stroke, heavy) {
+            (Stroke::Dashed, false) => "┆┄",
+            (Stroke::Dashed, true) => "┇┅",
+            (Stroke::Dotted, false) => "┊┈",
+            (Stroke::Dotted, true) => "┋┉",
+            _ => "",
+        };
+        broken
+            .chars()
+            .nth(index)
+            .or_else(|| glyphs.chars().nth(index))
     }
 }
 
+/// The braille dot column that each subpixel column of a character maps to
+/// in Canvas::write_braille().
+const BRAILLE_COLUMNS: [usize; 3] = [0, 0, 1];
+
+/// The braille dot row that each subpixel row of a character maps to in
+/// Canvas::write_braille().
+const BRAILLE_ROWS: [usize; 5] = [0, 1, 2, 2, 3];
+
+/// The bit of the braille pattern block (U+2800 onwards) that represents the
+/// dot at each row and column.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
 /// A character in the canvas. Either a 3x5 pixel map or a textual character.
 /// Textual characters always take precedence over line art.
 #[derive(Clone, Copy)]
@@ -142,6 +319,109 @@ impl Default for Character {
     }
 }
 
+/// Stroke styles for line art, set with Canvas::set_stroke(). The pixels
+/// are always drawn solid, such that outlines can still be found; the style
+/// only affects the characters the line art is rendered with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stroke {
+    #[default]
+    Solid,
+
+    /// Every other character along the line is left out, or the line is
+    /// drawn using dashed box-drawing characters like ┄.
+    Dashed,
+
+    /// The line is drawn using dots, or dotted box-drawing characters
+    /// like ┈.
+    Dotted,
+
+    /// The line is drawn using = and #, or heavy box-drawing characters.
+    Bold,
+}
+
+/// Shades for filling the interior of shapes with Canvas::fill().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shade {
+    /// Rendered as ░, or . in ASCII.
+    Light,
+
+    /// Rendered as ▒, or : in ASCII.
+    Medium,
+
+    /// Rendered as ▓, or # in ASCII.
+    Dark,
+}
+
+impl Shade {
+    /// Returns the shade that best represents the given fill color, where
+    /// darker colors get denser shades.
+    pub fn for_color(color: Rgb) -> Shade {
+        let luma = 299 * color.r as u32 + 587 * color.g as u32 + 114 * color.b as u32;
+        match luma / 1000 {
+            170.. => Shade::Light,
+            85..=169 => Shade::Medium,
+            _ => Shade::Dark,
+        }
+    }
+
+    /// Returns the character for this shade, using either Unicode block
+    /// elements or ASCII characters.
+    fn glyph(self, unicode: bool) -> char {
+        match (self, unicode) {
+            (Shade::Light, true) => '░',
+            (Shade::Medium, true) => '▒',
+            (Shade::Dark, true) => '▓',
+            (Shade::Light, false) => '.',
+            (Shade::Medium, false) => ':',
+            (Shade::Dark, false) => '#',
+        }
+    }
+}
+
+/// The style of a character in the canvas.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct CellStyle {
+    /// The foreground color, or None for the default color of the terminal.
+    foreground: Option<Rgb>,
+
+    /// The background color, or None for the default color of the terminal.
+    background: Option<Rgb>,
+
+    /// The stroke style of the line art. Characters where lines of different
+    /// styles meet are solid.
+    stroke: Stroke,
+
+    /// Whether this character is drawn for dashed lines, which skip every
+    /// other character.
+    dash: bool,
+
+    /// Whether corners in this character should be rounded.
+    rounded: bool,
+
+    /// The shade to render this character with if it is empty, for the
+    /// interior of filled shapes.
+    shade: Option<Shade>,
+
+    /// Whether line art is kept out of this character, see Canvas::clip().
+    clipped: bool,
+}
+
+/// How to size a canvas for a graph, see Canvas::for_graph().
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sizing {
+    /// Scale the graph to span the given number of columns.
+    Columns(usize),
+
+    /// Scale the graph to the given number of columns per inch.
+    Density(f64),
+}
+
+impl Default for Sizing {
+    fn default() -> Self {
+        Sizing::Density(COLUMNS_PER_INCH)
+    }
+}
+
 /// ASCII art canvas.
 ///
 /// The mapping from float coordinates to character coordinates is as follows:
@@ -155,6 +435,9 @@ impl Default for Character {
 ///  - Column = floor(x * scale.x)
 ///  - Row = floor(y * scale.y)
 ///
+/// After cropping, columns and rows are counted from the top-left corner of
+/// what is left, while pixel coordinates stay the same.
+///
 /// Writing a normal (text) character to a character position overrides any
 /// bitmap information for that position. If, in the end, only a bitmap is left
 /// for some position, a suitable character is chosen based on a BitmapFont
@@ -168,6 +451,16 @@ pub struct Canvas {
     /// Width of the data buffer.
     width: usize,
 
+    /// Height of the canvas in characters, or None if it grows downwards to
+    /// fit whatever is drawn. Anything drawn below a fixed height is
+    /// discarded, like anything drawn beyond the width.
+    height: Option<usize>,
+
+    /// The character coordinate, as it would be without cropping, of the
+    /// top-left character. This is nonzero after crop() or trim(), and
+    /// negative where they added padding.
+    origin: (i64, i64),
+
     /// Scaling factor (x and y independently). For the default unit scale, a
     /// character is 3x5 coordinate units in size.
     scale: InputCoord,
@@ -175,22 +468,319 @@ pub struct Canvas {
     /// When labels are too long to fit in a text box, "[<num>]" will be
     /// written instead, where num is one plus the index in this vector.
     footnotes: Vec<String>,
+
+    /// Styles for each character in data. May be shorter than data, in which
+    /// case the remaining characters use the default style.
+    styles: Vec<CellStyle>,
+
+    /// The foreground color for anything drawn from now on.
+    pen: Option<Rgb>,
+
+    /// The stroke style for line art drawn from now on.
+    stroke: Stroke,
+
+    /// The number of characters the current stroke has passed through, and
+    /// the last one, used to alternate characters for dashed lines.
+    stroke_progress: (usize, Option<CharCoord>),
 }
 
 impl Canvas {
-    /// Creates a new canvas with the specified width.
+    /// Creates a new canvas with the specified width, which grows downwards
+    /// to fit whatever is drawn until set_height() is called.
     pub fn new(width: f64, scale: InputCoord) -> Canvas {
         Canvas {
             data: vec![],
             width: ((width / 3.0) as usize) + 1,
+            height: None,
+            origin: (0, 0),
             scale,
             footnotes: vec![],
+            styles: vec![],
+            pen: None,
+            stroke: Stroke::Solid,
+            stroke_progress: (0, None),
+        }
+    }
+
+    /// Creates a new canvas that fits the given graph, whose coordinates are
+    /// taken to be in inches as in graphviz' output. The scale is chosen
+    /// such that the graph keeps its proportions on screen: as character
+    /// cells are CELL_ASPECT times as high as they are wide, an inch spans
+    /// that many times fewer rows than columns. The canvas is exactly as
+    /// wide as the graph; its height follows from the scale.
+    pub fn for_graph(graph: &Graph, sizing: Sizing) -> Canvas {
+        let density = match sizing {
+            // The right edge of the graph goes in the middle of the last
+            // column rather than at the start of the one after it.
+            Sizing::Columns(columns) if graph.width > 0.0 => {
+                (columns as f64 - 0.5).max(0.0) / graph.width
+            }
+            Sizing::Columns(_) => COLUMNS_PER_INCH,
+            Sizing::Density(density) => density,
+        };
+        let scale = InputCoord::new(3.0 * density, 5.0 * density / CELL_ASPECT);
+        let mut canvas = Canvas::new(graph.width * scale.x, scale);
+        canvas.set_height(graph.height * scale.y);
+        canvas
+    }
+
+    /// Fixes the height of the canvas, given in pixels like the width given
+    /// to new(). Anything already drawn below it is discarded.
+    pub fn set_height(&mut self, height: f64) {
+        let height = ((height / 5.0) as usize) + 1;
+        self.height = Some(height);
+        self.data.truncate(height * self.width);
+        self.styles.truncate(height * self.width);
+    }
+
+    /// Returns the width of the canvas in characters.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the canvas in characters. For a canvas without
+    /// a fixed height, this is the number of rows drawn in so far.
+    pub fn height(&self) -> usize {
+        self.height.unwrap_or(self.data.len().div_ceil(self.width))
+    }
+
+    /// Returns the first and last column and row that contain anything, be
+    /// it text, line art or a fill, or None if the canvas is empty.
+    pub fn bounding_box(&self) -> Option<(CharCoord, CharCoord)> {
+        let mut bounds: Option<(CharCoord, CharCoord)> = None;
+        for (i, character) in self.data.iter().enumerate() {
+            let cell = self.styles.get(i).copied().unwrap_or_default();
+            let empty = match character {
+                Character::Text(c) => *c == ' ',
+                Character::Bitmap(l) => l.0 == 0,
+            } && cell.background.is_none()
+                && cell.shade.is_none();
+            if empty {
+                continue;
+            }
+            let index = CharCoord::new(i % self.width, i / self.width);
+            bounds = Some(match bounds {
+                None => (index, index),
+                Some((a, b)) => (
+                    CharCoord::new(a.x.min(index.x), a.y.min(index.y)),
+                    CharCoord::new(b.x.max(index.x), b.y.max(index.y)),
+                ),
+            });
+        }
+        bounds
+    }
+
+    /// Crops the canvas to the characters from a to b, inclusive, which
+    /// become its new width and height. Anything drawn afterwards still ends
+    /// up in the same place relative to what was drawn before.
+    pub fn crop(&mut self, a: CharCoord, b: CharCoord) {
+        self.crop_to(
+            (a.x as i64, a.y as i64),
+            (b.x.max(a.x) as i64, b.y.max(a.y) as i64),
+        );
+    }
+
+    /// Removes empty rows and columns around the contents of the canvas on
+    /// all four sides, leaving the given number of them as padding. Padding
+    /// is added where the contents are closer to an edge than that. Empty
+    /// canvases are left as they are.
+    pub fn trim(&mut self, padding: usize) {
+        if let Some((a, b)) = self.bounding_box() {
+            let padding = padding as i64;
+            self.crop_to(
+                (a.x as i64 - padding, a.y as i64 - padding),
+                (b.x as i64 + padding, b.y as i64 + padding),
+            );
+        }
+    }
+
+    /// Implementation of crop() and trim(), where the corners may lie
+    /// outside the canvas.
+    fn crop_to(&mut self, a: (i64, i64), b: (i64, i64)) {
+        let width = (b.0 - a.0 + 1) as usize;
+        let height = (b.1 - a.1 + 1) as usize;
+        let mut data = Vec::with_capacity(width * height);
+        let mut styles = Vec::with_capacity(width * height);
+        for y in a.1..=b.1 {
+            for x in a.0..=b.0 {
+                let index = (usize::try_from(x), usize::try_from(y));
+                if let (Ok(x), Ok(y)) = index {
+                    data.push(self.get_character(CharCoord::new(x, y)));
+                    styles.push(self.get_style(CharCoord::new(x, y)));
+                } else {
+                    data.push(Character::default());
+                    styles.push(CellStyle::default());
+                }
+            }
+        }
+        self.data = data;
+        self.styles = styles;
+        self.width = width;
+        self.height = Some(height);
+        self.origin = (self.origin.0 + a.0, self.origin.1 + a.1);
+        self.stroke_progress = (self.stroke_progress.0, None);
+    }
+
+    /// Sets the foreground color for any line art and text drawn from now
+    /// on, or the default color of the terminal for None. Where multiple
+    /// things are drawn in the same character, the last one determines its
+    /// color.
+    pub fn set_pen_color(&mut self, color: Option<Rgb>) {
+        self.pen = color;
+    }
+
+    /// Sets the stroke style for any line art drawn from now on. Dashes
+    /// start over whenever this is called.
+    pub fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+        self.stroke_progress = (0, None);
+    }
+
+    /// Fills the interior of a shape: all characters whose centers lie within
+    /// the rectangle spanned by the given coordinates and for which the given
+    /// function returns true. Coordinate a must be less than coordinate b in
+    /// both axes.
+    ///
+    /// The characters get the given background color, which is rendered if
+    /// colors are enabled. Otherwise, or if there is no color, the characters
+    /// that remain empty are rendered using the given shade, if any. Text is
+    /// drawn on top of the fill as usual, with the shade left out next to it
+    /// to keep the text readable.
+    pub fn fill<F: Fn(InputCoord) -> bool>(
+        &mut self,
+        a: InputCoord,
+        b: InputCoord,
+        color: Option<Rgb>,
+        shade: Option<Shade>,
+        contains: F,
+    ) {
+        for index in self.cells_within(a, b, contains) {
+            if let Some(cell) = self.get_style_mut(index) {
+                cell.background = color;
+                cell.shade = shade;
+            }
+        }
+    }
+
+    /// Keeps line art drawn from now on out of the interior of a shape, given
+    /// in the same way as for fill(). Characters that already contain line
+    /// art, like the outline of the shape, are not part of the interior, so
+    /// lines can still connect to them. This is meant for edges, which should
+    /// end at the outline of a node without running into its label.
+    pub fn clip<F: Fn(InputCoord) -> bool>(&mut self, a: InputCoord, b: InputCoord, contains: F) {
+        for index in self.cells_within(a, b, contains) {
+            if let Character::Bitmap(BitmapChar(0)) = self.get_character(index) {
+                if let Some(cell) = self.get_style_mut(index) {
+                    cell.clipped = true;
+                }
+            }
+        }
+    }
+
+    /// Removes all clipping set up by clip().
+    pub fn clear_clip(&mut self) {
+        for cell in self.styles.iter_mut() {
+            cell.clipped = false;
+        }
+    }
+
+    /// Returns the characters whose centers lie within the rectangle spanned
+    /// by the given coordinates and for which the given function returns
+    /// true.
+    fn cells_within<F: Fn(InputCoord) -> bool>(
+        &self,
+        a: InputCoord,
+        b: InputCoord,
+        contains: F,
+    ) -> Vec<CharCoord> {
+        let size = self.char_size();
+        let first = (a.x / size.x).floor() as i64;
+        let last = (b.x / size.x).floor() as i64;
+        let mut cells = vec![];
+        for y in (a.y / size.y).floor() as i64..=(b.y / size.y).floor() as i64 {
+            for x in first..=last {
+                let center = InputCoord::new((x as f64 + 0.5) * size.x, (y as f64 + 0.5) * size.y);
+                if center.x >= a.x
+                    && center.x <= b.x
+                    && center.y >= a.y
+                    && center.y <= b.y
+                    && contains(center)
+                {
+                    cells.extend(self.cell_index(x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Returns a mutable reference to the style of the character at the
+    /// given character coordinate, if it exists.
+    fn get_style_mut(&mut self, index: CharCoord) -> Option<&mut CellStyle> {
+        let index = self.data_index(index)?;
+        if index >= self.styles.len() {
+            self.styles.resize(index + 1, CellStyle::default());
+        }
+        if index >= self.data.len() {
+            self.data.resize(index + 1, Character::default());
+        }
+        Some(&mut self.styles[index])
+    }
+
+    /// Returns the style of the character at the given character coordinate.
+    fn get_style(&self, index: CharCoord) -> CellStyle {
+        self.data_index(index)
+            .and_then(|i| self.styles.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Applies the pen color to the character at the given character
+    /// coordinate.
+    fn paint(&mut self, index: CharCoord) {
+        let pen = self.pen;
+        if let Some(cell) = self.get_style_mut(index) {
+            cell.foreground = pen;
+        }
+    }
+
+    /// Applies the pen color and stroke style to the character at the given
+    /// character coordinate, after a pixel of line art was drawn in it. The
+    /// stroke style only applies if the character was empty before, as
+    /// characters where lines of different styles meet are drawn solid.
+    fn paint_stroke(&mut self, index: CharCoord, was_empty: bool) {
+        self.paint(index);
+        let (mut count, last) = self.stroke_progress;
+        if last != Some(index) {
+            count += 1;
+            self.stroke_progress = (count, Some(index));
+        }
+        let stroke = self.stroke;
+        if let Some(cell) = self.get_style_mut(index) {
+            if was_empty {
+                cell.stroke = stroke;
+                cell.dash = count % 2 == 1;
+            } else if cell.stroke != stroke {
+                cell.stroke = Stroke::Solid;
+            }
+        }
+    }
+
+    /// Returns the scale factor from input coordinates to pixels.
+    pub fn scale(&self) -> InputCoord {
+        self.scale
+    }
+
+    /// Returns the size of a single character in input coordinates.
+    pub fn char_size(&self) -> InputCoord {
+        InputCoord {
+            x: 3.0 / self.scale.x,
+            y: 5.0 / self.scale.y,
         }
     }
 
     /// Returns the index in data for a given character coordinate.
     fn data_index(&self, index: CharCoord) -> Option<usize> {
-        if index.x >= self.width {
+        if index.x >= self.width || self.height.is_some_and(|height| index.y >= height) {
             None
         } else {
             Some(index.x + self.width * index.y)
@@ -228,21 +818,39 @@ impl Canvas {
     /// Translates a floating-point coordinate to a character coordinate and a
     /// sub-character coordinate on a 3x5 grid per character.
     fn translate_pix_to_char(&self, coord: PixelCoord) -> Option<(CharCoord, i8, i8)> {
-        if coord.x < 0 || coord.y < 0 {
-            return None;
-        }
-        let cx = (coord.x / 3) as usize;
-        let cy = (coord.y / 5) as usize;
-        let px = (coord.x % 3) as i8;
-        let py = (coord.y % 5) as i8;
-        Some((CharCoord { x: cx, y: cy }, px, py))
+        let index = self.cell_index(coord.x.div_euclid(3), coord.y.div_euclid(5))?;
+        let px = coord.x.rem_euclid(3) as i8;
+        let py = coord.y.rem_euclid(5) as i8;
+        Some((index, px, py))
+    }
+
+    /// Translates the column and row of a character as it would be without
+    /// cropping to a character coordinate, if it lies within the canvas.
+    fn cell_index(&self, x: i64, y: i64) -> Option<CharCoord> {
+        let x = usize::try_from(x - self.origin.0).ok()?;
+        let y = usize::try_from(y - self.origin.1).ok()?;
+        Some(CharCoord::new(x, y))
     }
 
     /// Translates a floating-point coordinate to a pixel coordinate.
     fn translate_in_to_pix(&self, coord: InputCoord) -> PixelCoord {
+        Canvas::round_pix(self.scale_in_to_pix(coord))
+    }
+
+    /// Scales a floating-point coordinate to pixel units without rounding.
+    fn scale_in_to_pix(&self, coord: InputCoord) -> InputCoord {
+        InputCoord {
+            x: coord.x * self.scale.x,
+            y: coord.y * self.scale.y,
+        }
+    }
+
+    /// Rounds a floating-point coordinate in pixel units to the pixel it lies
+    /// in.
+    fn round_pix(coord: InputCoord) -> PixelCoord {
         PixelCoord {
-            x: (coord.x * self.scale.x) as i64,
-            y: (coord.y * self.scale.y) as i64,
+            x: coord.x as i64,
+            y: coord.y as i64,
         }
     }
 
@@ -250,6 +858,7 @@ impl Canvas {
     fn set_character(&mut self, index: CharCoord, character: char) {
         self.get_character_mut(index)
             .map(|x| *x = Character::Text(character));
+        self.paint(index);
     }
 
     /// Returns the state of the pixel at the given a pixel coordinate. Returns
@@ -264,11 +873,19 @@ impl Canvas {
         false
     }
 
-    /// Draws a single pixel, given a pixel coordinate.
+    /// Draws a single pixel, given a pixel coordinate. Pixels in clipped
+    /// characters are left alone.
     fn set_pixel(&mut self, coord: PixelCoord, value: bool) {
         if let Some((index, x, y)) = self.translate_pix_to_char(coord) {
+            if self.get_style(index).clipped {
+                return;
+            }
             if let Some(Character::Bitmap(l)) = self.get_character_mut(index) {
+                let was_empty = l.0 == 0;
                 l.poke(x, y, value);
+                if value {
+                    self.paint_stroke(index, was_empty);
+                }
             }
         }
     }
@@ -289,18 +906,218 @@ impl Canvas {
         }
     }
 
+    /// Writes a single line of text starting at the given character
+    /// coordinate. Characters that would end up left of the canvas are
+    /// skipped.
+    fn draw_text_line(&mut self, row: usize, column: isize, text: &str) {
+        for (i, char) in text.chars().filter(|c| !c.is_control()).enumerate() {
+            if let Ok(x) = usize::try_from(column + i as isize) {
+                self.set_character(CharCoord::new(x, row), char);
+            }
+        }
+    }
+
+    /// Adds a footnote, returning the marker that should be written in its
+    /// place. Newlines in the text are replaced with spaces, as a footnote is
+    /// rendered as a single line.
+    fn add_footnote(&mut self, text: &str) -> String {
+        self.footnotes.push(text.replace('\n', " "));
+        format!("[{}]", self.footnotes.len())
+    }
+
+    /// Word-wraps the given text to the given width, treating newlines as
+    /// forced line breaks. Returns None if a single word does not fit.
+    fn wrap_text(text: &str, width: usize) -> Option<Vec<String>> {
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            if paragraph.chars().filter(|c| !c.is_control()).count() <= width {
+                lines.push(paragraph.to_string());
+                continue;
+            }
+            let mut line = String::new();
+            let mut line_len = 0;
+            for word in paragraph.split_whitespace() {
+                let word_len = word.chars().filter(|c| !c.is_control()).count();
+                if word_len > width {
+                    return None;
+                }
+                if line_len > 0 && line_len + 1 + word_len > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_len = 0;
+                }
+                if line_len > 0 {
+                    line.push(' ');
+                    line_len += 1;
+                }
+                line.push_str(word);
+                line_len += word_len;
+            }
+            lines.push(line);
+        }
+        Some(lines)
+    }
+
+    /// Writes a label centered within the rectangle spanned by the given
+    /// coordinates, not including the characters containing its outline.
+    /// Coordinate a must be less than coordinate b in both axes. Newlines in
+    /// the label force a line break, and lines that are too wide are
+    /// word-wrapped. If the label still does not fit, a footnote marker is
+    /// written in its place instead, and the label is added to the footnotes.
+    pub fn draw_label(&mut self, a: InputCoord, b: InputCoord, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let (Some((a, _, _)), Some((b, _, _))) =
+            (self.translate_in_to_char(a), self.translate_in_to_char(b))
+        else {
+            return;
+        };
+        let width = b.x.saturating_sub(a.x + 1);
+        let height = b.y.saturating_sub(a.y + 1);
+        let lines = Canvas::wrap_text(text, width)
+            .filter(|lines| lines.len() <= height)
+            .unwrap_or_else(|| vec![self.add_footnote(text)]);
+        let top = (a.y + b.y + 1 - lines.len()) / 2;
+        for (row, line) in lines.iter().enumerate() {
+            let left = (a.x + b.x + 1) as isize - line.chars().count() as isize;
+            self.draw_text_line(top + row, left / 2, line);
+        }
+    }
+
+    /// Returns whether the given line of character positions lies within the
+    /// canvas and does not contain any text characters yet.
+    /// One character of margin is required on either side of existing text,
+    /// such that separate pieces of text do not run into each other.
+    fn is_text_free(&self, row: usize, column: isize, width: usize) -> bool {
+        let Ok(left) = usize::try_from(column) else {
+            return false;
+        };
+        if left + width > self.width || self.height.is_some_and(|height| row >= height) {
+            return false;
+        }
+        (left.saturating_sub(1)..left + width + 1).all(|x| {
+            !matches!(
+                self.get_character(CharCoord::new(x, row)),
+                Character::Text(_)
+            )
+        })
+    }
+
+    /// Tries to place the given lines of text centered on the given
+    /// character coordinate, or one row above or below it. Returns whether
+    /// this succeeded, i.e. if there was room without overwriting any
+    /// existing text.
+    fn try_draw_centered(&mut self, center: CharCoord, lines: &[&str]) -> bool {
+        for offset in [0, -1, 1] {
+            let Ok(top) = usize::try_from(center.y as isize + offset - lines.len() as isize / 2)
+            else {
+                continue;
+            };
+            let placement: Vec<_> = lines
+                .iter()
+                .enumerate()
+                .map(|(row, line)| {
+                    let width = line.chars().count();
+                    (top + row, center.x as isize - width as isize / 2, width)
+                })
+                .collect();
+            if placement
+                .iter()
+                .all(|&(row, column, width)| self.is_text_free(row, column, width))
+            {
+                for (&(row, column, _), line) in placement.iter().zip(lines) {
+                    self.draw_text_line(row, column, line);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Writes a free-standing label centered on the given coordinate, such as
+    /// an edge label. Newlines in the label force a line break. The label is
+    /// moved up or down by a row if it would otherwise overwrite existing
+    /// text. If that does not help, or if the label does not fit within the
+    /// canvas, a footnote marker is written in its place instead, and the
+    /// label is added to the footnotes.
+    pub fn draw_centered_label(&mut self, center: InputCoord, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let Some((center, _, _)) = self.translate_in_to_char(center) else {
+            return;
+        };
+        let lines: Vec<&str> = text.split('\n').collect();
+        if !self.try_draw_centered(center, &lines) {
+            let marker = self.add_footnote(text);
+            if !self.try_draw_centered(center, &[&marker]) {
+                // Out of options; just make sure the marker is visible.
+                let width = marker.chars().count() as isize;
+                let left = (center.x as isize - width / 2)
+                    .min(self.width as isize - width)
+                    .max(0);
+                self.draw_text_line(center.y, left, &marker);
+            }
+        }
+    }
+
+    /// Writes a single line of text at exactly the given position, without
+    /// regard for anything already on the canvas. The coordinate specifies
+    /// the vertical center of the line, and its horizontal anchor point
+    /// according to the alignment.
+    pub fn draw_aligned_text(&mut self, coord: InputCoord, align: Align, text: &str) {
+        let pix = self.translate_in_to_pix(coord);
+        let Ok(row) = usize::try_from(pix.y.div_euclid(5) - self.origin.1) else {
+            return;
+        };
+        let width = text.chars().filter(|c| !c.is_control()).count() as isize;
+        let column = (pix.x.div_euclid(3) - self.origin.0) as isize;
+        let left = match align {
+            Align::Left => column,
+            Align::Center => column - width / 2,
+            Align::Right => column + 1 - width,
+        };
+        self.draw_text_line(row, left, text);
+    }
+
     /// Draws a rectangle. Coordinate a must be less than coordinate b in both
     /// axes.
     pub fn draw_rect(&mut self, a: InputCoord, b: InputCoord) {
         let a = self.translate_in_to_pix(a);
         let b = self.translate_in_to_pix(b);
-        for x in a.x..=b.x {
-            self.set_pixel(PixelCoord { x, y: a.y }, true);
-            self.set_pixel(PixelCoord { x, y: b.y }, true);
+        let corners = [
+            a,
+            PixelCoord { x: b.x, y: a.y },
+            b,
+            PixelCoord { x: a.x, y: b.y },
+            a,
+        ];
+        for pair in corners.windows(2) {
+            self.draw_pix_line(pair[0], pair[1]);
         }
-        for y in a.y..=b.y {
-            self.set_pixel(PixelCoord { x: a.x, y }, true);
-            self.set_pixel(PixelCoord { x: b.x, y }, true);
+    }
+
+    /// Draws a rectangle with rounded corners. Coordinate a must be less than
+    /// coordinate b in both axes. The corners are rendered using . and ', or
+    /// rounded box-drawing characters.
+    pub fn draw_rounded_rect(&mut self, a: InputCoord, b: InputCoord) {
+        self.draw_rect(a, b);
+        let a = self.translate_in_to_pix(a);
+        let b = self.translate_in_to_pix(b);
+        for (x, y) in [(a.x, a.y), (b.x, a.y), (a.x, b.y), (b.x, b.y)] {
+            let Some((index, _, _)) = self.translate_pix_to_char(PixelCoord { x, y }) else {
+                continue;
+            };
+            if let Some(cell) = self.get_style_mut(index) {
+                cell.rounded = true;
+            }
+        }
+    }
+
+    /// Draws a line between two pixel coordinates.
+    fn draw_pix_line(&mut self, a: PixelCoord, b: PixelCoord) {
+        for (x, y) in line_drawing::Bresenham::new((a.x, a.y), (b.x, b.y)) {
+            self.set_pixel(PixelCoord { x, y }, true);
         }
     }
 
@@ -308,36 +1125,515 @@ impl Canvas {
     pub fn draw_line(&mut self, a: InputCoord, b: InputCoord) {
         let a = self.translate_in_to_pix(a);
         let b = self.translate_in_to_pix(b);
-        for (x, y) in line_drawing::Bresenham::new((a.x, a.y), (b.x, b.y)) {
-            self.set_pixel(PixelCoord { x, y }, true);
+        self.draw_pix_line(a, b);
+    }
+
+    /// Draws a sequence of connected straight lines.
+    pub fn draw_polyline(&mut self, points: &[InputCoord]) {
+        let points: Vec<_> = points
+            .iter()
+            .map(|p| self.translate_in_to_pix(*p))
+            .collect();
+        for pair in points.windows(2) {
+            self.draw_pix_line(pair[0], pair[1]);
         }
     }
 
-    /// Renders to a string with a given font.
+    /// Draws a closed polygon.
+    pub fn draw_polygon(&mut self, points: &[InputCoord]) {
+        self.draw_polyline(points);
+        if let (Some(first), Some(last)) = (points.first(), points.last()) {
+            self.draw_line(*last, *first);
+        }
+    }
+
+    /// Draws part of an ellipse with the given center and radii, from the
+    /// given start angle to the given end angle in radians. An angle of zero
+    /// corresponds to the positive X axis, and angles increase towards the
+    /// positive Y axis. The arc is flattened in pixel space with about one
+    /// sample per pixel of arc length.
+    pub fn draw_arc(&mut self, center: InputCoord, radii: InputCoord, start: f64, end: f64) {
+        let center = self.scale_in_to_pix(center);
+        let radii = self.scale_in_to_pix(radii);
+        let circumference =
+            std::f64::consts::TAU * ((radii.x * radii.x + radii.y * radii.y) / 2.0).sqrt();
+        let sweep = end - start;
+        let steps = (circumference * sweep.abs() / std::f64::consts::TAU)
+            .ceil()
+            .max(4.0) as usize;
+        let point = |angle: f64| {
+            Canvas::round_pix(InputCoord {
+                x: center.x + radii.x * angle.cos(),
+                y: center.y + radii.y * angle.sin(),
+            })
+        };
+        let mut prev = point(start);
+        self.set_pixel(prev, true);
+        for step in 1..=steps {
+            let p = point(start + sweep * step as f64 / steps as f64);
+            if (p.x, p.y) != (prev.x, prev.y) {
+                self.draw_pix_line(prev, p);
+                prev = p;
+            }
+        }
+    }
+
+    /// Draws an ellipse with the given center and radii.
+    pub fn draw_ellipse(&mut self, center: InputCoord, radii: InputCoord) {
+        self.draw_arc(center, radii, 0.0, std::f64::consts::TAU);
+    }
+
+    /// Draws a piecewise cubic Bézier curve. The first control point is the
+    /// start of the curve, after which each group of three control points
+    /// describes the next segment: two off-curve control points followed by
+    /// the end point of the segment. This is the format graphviz uses for
+    /// edge splines (it calls them B-splines, but the control points are
+    /// really meant for Bézier segments). Any remaining points that do not
+    /// form a complete segment are drawn as straight lines.
+    ///
+    /// The curve is flattened in pixel space with about one sample per pixel
+    /// of control polygon length, such that the result follows the curve at
+    /// the full 3x5 subpixel resolution.
+    pub fn draw_bezier(&mut self, cpts: &[InputCoord]) {
+        let Some(first) = cpts.first() else {
+            return;
+        };
+        let mut start = self.scale_in_to_pix(*first);
+        let mut prev = Canvas::round_pix(start);
+        self.set_pixel(prev, true);
+        let mut segments = cpts[1..].chunks_exact(3);
+        for segment in segments.by_ref() {
+            let p0 = start;
+            let p1 = self.scale_in_to_pix(segment[0]);
+            let p2 = self.scale_in_to_pix(segment[1]);
+            let p3 = self.scale_in_to_pix(segment[2]);
+            let length = distance(p0, p1) + distance(p1, p2) + distance(p2, p3);
+            let steps = length.ceil().max(1.0) as usize;
+            for step in 1..=steps {
+                let t = step as f64 / steps as f64;
+                let u = 1.0 - t;
+                let p = p0 * (u * u * u)
+                    + p1 * (3.0 * u * u * t)
+                    + p2 * (3.0 * u * t * t)
+                    + p3 * (t * t * t);
+                let p = Canvas::round_pix(p);
+                if (p.x, p.y) != (prev.x, prev.y) {
+                    self.draw_pix_line(prev, p);
+                    prev = p;
+                }
+            }
+            start = p3;
+        }
+        for b in segments.remainder() {
+            let b = self.translate_in_to_pix(*b);
+            self.draw_pix_line(prev, b);
+            prev = b;
+        }
+    }
+
+    /// Draws a straight line that ends in an arrowhead, from the given
+    /// starting coordinate towards the given tip coordinate. The tip is
+    /// usually a point on the outline of a previously drawn shape. The line
+    /// stops at the first pixel that was already drawn near the tip, such that
+    /// rounding errors cannot make it stop short of or overshoot the outline.
+    /// The arrowhead is a text character (one of `>`, `<`, `^` and `v`) placed
+    /// in the character right before the one containing the outline, such
+    /// that it touches the outline without overwriting it.
+    pub fn draw_arrow(&mut self, from: InputCoord, tip: InputCoord) {
+        self.draw_to_outline(from, tip, true);
+    }
+
+    /// Draws a straight line from the given starting coordinate towards the
+    /// given tip coordinate, stopping at the outline near the tip in the same
+    /// way as draw_arrow(), but without an arrowhead.
+    pub fn draw_line_to_outline(&mut self, from: InputCoord, tip: InputCoord) {
+        self.draw_to_outline(from, tip, false);
+    }
+
+    /// Implementation of draw_arrow() and draw_line_to_outline().
+    fn draw_to_outline(&mut self, from: InputCoord, tip: InputCoord, arrow: bool) {
+        let a = self.scale_in_to_pix(from);
+        let b = self.scale_in_to_pix(tip);
+        let length = distance(a, b);
+        if length == 0.0 {
+            return;
+        }
+
+        // Walk a bit past the tip, to account for rounding errors, and stop
+        // at the first pixel that is already set. Pixels that are already set
+        // at the start of the path are assumed to belong to whatever line
+        // leads up to the arrow.
+        let c = Canvas::round_pix(b + (b - a) * (2.0 / length));
+        let a = Canvas::round_pix(a);
+        let mut outline = Canvas::round_pix(b);
+        let mut line = vec![];
+        for (x, y) in line_drawing::Bresenham::new((a.x, a.y), (c.x, c.y)).skip(1) {
+            let pixel = PixelCoord { x, y };
+            if self.get_pixel(pixel) {
+                if !line.is_empty() {
+                    outline = pixel;
+                    break;
+                }
+            } else {
+                line.push(pixel);
+            }
+        }
+
+        // If the outline runs horizontally where we hit it, the arrow should
+        // point vertically and vice versa. If this is unclear, use the
+        // dominant direction of the line in character units.
+        let horizontal_outline = self.get_pixel(outline - PixelCoord::new(1, 0))
+            || self.get_pixel(outline + PixelCoord::new(1, 0));
+        let vertical_outline = self.get_pixel(outline - PixelCoord::new(0, 1))
+            || self.get_pixel(outline + PixelCoord::new(0, 1));
+        let dx = (tip.x - from.x) * self.scale.x / 3.0;
+        let dy = (tip.y - from.y) * self.scale.y / 5.0;
+        let horizontal = match (horizontal_outline, vertical_outline) {
+            (true, false) => false,
+            (false, true) => true,
+            _ => dx.abs() >= dy.abs(),
+        };
+
+        // Draw the line up to the character containing the outline. Pixels
+        // within that character are left alone, as they would only distort
+        // the outline glyph.
+        let Some((index, _, _)) = self.translate_pix_to_char(outline) else {
+            return;
+        };
+        for pixel in line {
+            match self.translate_pix_to_char(pixel) {
+                Some((i, _, _)) if (i.x, i.y) == (index.x, index.y) => break,
+                _ => self.set_pixel(pixel, true),
+            }
+        }
+        if !arrow {
+            return;
+        }
+
+        // Place the arrowhead glyph next to the character containing the
+        // outline.
+        let (index, glyph) = match (horizontal, dx > 0.0, dy > 0.0) {
+            (true, true, _) => (
+                index.x.checked_sub(1).map(|x| CharCoord::new(x, index.y)),
+                '>',
+            ),
+            (true, false, _) => (Some(CharCoord::new(index.x + 1, index.y)), '<'),
+            (false, _, true) => (
+                index.y.checked_sub(1).map(|y| CharCoord::new(index.x, y)),
+                'v',
+            ),
+            (false, _, false) => (Some(CharCoord::new(index.x, index.y + 1)), '^'),
+        };
+        if let Some(index) = index {
+            self.set_character(index, glyph);
+        }
+    }
+
+    /// Renders the canvas as text with the given font and options, followed
+    /// by the footnotes, if any.
+    pub fn render_to(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        font: &BitmapFont,
+        options: &RenderOptions,
+    ) -> std::fmt::Result {
+        let color = options.color_mode;
+        match options.charset {
+            Charset::Ascii => self.write_ascii(output, font, color),
+            Charset::BoxDrawing(style) => self.write_box_drawing(output, font, style, color),
+            Charset::Braille => self.write_braille(output, color),
+        }
+    }
+
+    /// Renders to a string with a given font. See render_to() for more
+    /// options.
     pub fn render(
         &self,
         output: &mut std::fmt::Formatter<'_>,
         font: &BitmapFont,
     ) -> std::fmt::Result {
-        let mut it = self.data.iter();
-        let mut done = false;
-        let mut line = String::with_capacity(self.width);
-        while !done {
-            for _ in 0..self.width {
-                match it.next() {
-                    Some(Character::Text(c)) => {
-                        line.push(*c);
-                    }
-                    Some(Character::Bitmap(l)) => {
-                        line.push(font.translate(*l));
-                    }
-                    None => {
-                        done = true;
-                        break;
+        self.write_ascii(output, font, None)
+    }
+
+    /// Renders the canvas to a string, see render_to().
+    pub fn render_to_string(&self, font: &BitmapFont, options: &RenderOptions) -> String {
+        let mut output = String::new();
+        // Writing to a string cannot fail.
+        let _ = self.render_to(&mut output, font, options);
+        output
+    }
+
+    /// Renders the canvas to the given writer, see render_to(). The output is
+    /// written line by line as it is rendered.
+    pub fn write_to<W: std::io::Write>(
+        &self,
+        output: W,
+        font: &BitmapFont,
+        options: &RenderOptions,
+    ) -> std::io::Result<()> {
+        let mut output = IoWriter {
+            inner: output,
+            error: None,
+        };
+        match self.render_to(&mut output, font, options) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(output
+                .error
+                .unwrap_or_else(|| std::io::Error::other("failed to render canvas"))),
+        }
+    }
+
+    /// Renders using ASCII characters, with the given font for line art.
+    fn write_ascii(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        font: &BitmapFont,
+        color: Option<ColorMode>,
+    ) -> std::fmt::Result {
+        self.render_with(output, color, false, |index, pixels| {
+            let cell = self.get_style(index);
+            if cell.stroke == Stroke::Solid && !cell.rounded {
+                return font.translate(pixels);
+            }
+            let arms = self.box_arms(index, pixels);
+            let corner = arms.is_some_and(|[up, down, left, right]| up != down && left != right);
+            match (cell.stroke, arms) {
+                (Stroke::Dotted, Some([true, true, false, false])) => ':',
+                (Stroke::Bold, Some([false, false, true, true])) => '=',
+                (Stroke::Bold, Some(_)) => '#',
+                (_, Some([_, down, _, _])) if cell.rounded && corner => {
+                    if down {
+                        '.'
+                    } else {
+                        '\''
                     }
                 }
+                _ => Canvas::broken_stroke(cell, pixels).unwrap_or_else(|| font.translate(pixels)),
             }
-            writeln!(output, "{}", line.trim_end())?;
+        })
+    }
+
+    /// Returns the character for a character of line art that is part of a
+    /// dashed or dotted line, if it does not depend on the shape of the line:
+    /// a space for the gaps in dashed lines, or a dot for dotted lines.
+    fn broken_stroke(cell: CellStyle, pixels: BitmapChar) -> Option<char> {
+        match cell.stroke {
+            Stroke::Dashed if !cell.dash => Some(' '),
+            Stroke::Dotted if pixels.0 != 0 => Some('.'),
+            _ => None,
+        }
+    }
+
+    /// Renders using Unicode box-drawing characters of the given style, see
+    /// Charset::BoxDrawing.
+    fn write_box_drawing(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        font: &BitmapFont,
+        style: BoxStyle,
+        color: Option<ColorMode>,
+    ) -> std::fmt::Result {
+        self.render_with(output, color, true, |index, pixels| {
+            let cell = self.get_style(index);
+            self.box_arms(index, pixels)
+                .and_then(|arms| style.glyph(arms, cell))
+                .or_else(|| Canvas::broken_stroke(cell, pixels))
+                .unwrap_or_else(|| font.translate(pixels))
+        })
+    }
+
+    /// Returns whether the character at the given coordinate continues a
+    /// line leaving a neighboring character through the given side of the
+    /// neighbor, at the given pixel position along that side. The sides are
+    /// numbered up, down, left, right. The line has to continue for at least
+    /// the given number of pixels. Arrowheads continue any line, such that
+    /// lines running into them keep their arms.
+    fn continues_line(
+        &self,
+        index: Option<CharCoord>,
+        side: usize,
+        position: i8,
+        depth: i8,
+    ) -> bool {
+        let Some(index) = index else {
+            return false;
+        };
+        match self.get_character(index) {
+            Character::Text(c) => matches!(c, '<' | '>' | '^' | 'v'),
+            Character::Bitmap(pixels) => (0..depth).all(|i| match side {
+                0 => pixels.peek(position, 4 - i),
+                1 => pixels.peek(position, i),
+                2 => pixels.peek(2 - i, position),
+                _ => pixels.peek(i, position),
+            }),
+        }
+    }
+
+    /// Determines which arms (up, down, left, right) a box-drawing character
+    /// would need to represent the given bitmap at the given character
+    /// coordinate. This requires all pixels to lie on a single row and/or a
+    /// single column, each forming a contiguous run, with the two crossing
+    /// if both are present. An arm exists where a run reaches the side of
+    /// the character and the line continues in the neighboring character,
+    /// and a run that ends on a side may also turn into its neighbor to form
+    /// a corner. Straight lines need both arms, to avoid turning the flat
+    /// parts of curves into box-drawing characters. Returns None if the
+    /// bitmap cannot be represented this way.
+    fn box_arms(&self, index: CharCoord, pixels: BitmapChar) -> Option<[bool; 4]> {
+        let set: Vec<(i8, i8)> = (0..5)
+            .flat_map(|y| (0..3).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixels.peek(x, y))
+            .collect();
+        let first = *set.first()?;
+
+        // Find the row and column the pixels lie on, if any.
+        let (row, column) = if set.iter().all(|&(_, y)| y == first.1) {
+            (Some(first.1), None)
+        } else if set.iter().all(|&(x, _)| x == first.0) {
+            (None, Some(first.0))
+        } else {
+            let (row, column) =
+                (0..5)
+                    .flat_map(|y| (0..3).map(move |x| (y, x)))
+                    .find(|&(y, x)| {
+                        pixels.peek(x, y) && set.iter().all(|&(px, py)| px == x || py == y)
+                    })?;
+            (Some(row), Some(column))
+        };
+
+        // Determine the extent of the runs, which must be contiguous.
+        let run = |along: Vec<i8>| -> Option<(i8, i8)> {
+            let (min, max) = (*along.iter().min()?, *along.iter().max()?);
+            if (max - min + 1) as usize == along.len() {
+                Some((min, max))
+            } else {
+                None
+            }
+        };
+        let neighbor = |dx: isize, dy: isize| {
+            let x = usize::try_from(index.x as isize + dx).ok()?;
+            let y = usize::try_from(index.y as isize + dy).ok()?;
+            Some(CharCoord::new(x, y))
+        };
+        let row_run = match row {
+            Some(row) => Some(run(set
+                .iter()
+                .filter(|p| p.1 == row)
+                .map(|p| p.0)
+                .collect())?),
+            None => None,
+        };
+        let column_run = match column {
+            Some(column) => Some(run(set
+                .iter()
+                .filter(|p| p.0 == column)
+                .map(|p| p.1)
+                .collect())?),
+            None => None,
+        };
+
+        let arms_with_depth = |depth: i8| {
+            let up = |x: i8| self.continues_line(neighbor(0, -1), 0, x, depth);
+            let down = |x: i8| self.continues_line(neighbor(0, 1), 1, x, depth);
+            let left = |y: i8| self.continues_line(neighbor(-1, 0), 2, y, depth);
+            let right = |y: i8| self.continues_line(neighbor(1, 0), 3, y, depth);
+            let mut arms = [false; 4];
+            if let (Some(row), Some((min, max))) = (row, row_run) {
+                arms[2] = min == 0 && left(row);
+                arms[3] = max == 2 && right(row);
+                if column.is_none() {
+                    // The ends of a horizontal run on the top or bottom of
+                    // the character may turn into a vertical line.
+                    arms[0] = row == 0 && (up(min) || up(max));
+                    arms[1] = row == 4 && (down(min) || down(max));
+                }
+            }
+            if let (Some(column), Some((min, max))) = (column, column_run) {
+                arms[0] = min == 0 && up(column);
+                arms[1] = max == 4 && down(column);
+                if row.is_none() {
+                    // Likewise for the ends of a vertical run on the left or
+                    // right of the character.
+                    arms[2] = column == 0 && (left(min) || left(max));
+                    arms[3] = column == 2 && (right(min) || right(max));
+                }
+            }
+            arms
+        };
+
+        let arms = arms_with_depth(1);
+        let horizontal = arms[2] as u8 + arms[3] as u8;
+        let vertical = arms[0] as u8 + arms[1] as u8;
+        let complete = match (row, column) {
+            (Some(_), None) => horizontal == 2 || (horizontal == 1 && vertical >= 1),
+            (None, Some(_)) => vertical == 2 || (vertical == 1 && horizontal >= 1),
+            _ => horizontal >= 1 && vertical >= 1,
+        };
+
+        // Corners need lines continuing for at least two pixels in both
+        // directions, such that steps in curves are not mistaken for them.
+        if !complete || (horizontal == 1 && vertical == 1 && arms_with_depth(2) != arms) {
+            None
+        } else {
+            Some(arms)
+        }
+    }
+
+    /// Returns the character to render an empty character with, if it lies
+    /// within a shape filled with a shade. Background colors take precedence
+    /// over shades where they are rendered, and there is no shade next to
+    /// text.
+    fn shade_at(
+        &self,
+        index: CharCoord,
+        cell: CellStyle,
+        color: Option<ColorMode>,
+        unicode: bool,
+    ) -> Option<char> {
+        let shade = cell.shade?;
+        if color.is_some() && cell.background.is_some() {
+            return None;
+        }
+        let is_text = |x: Option<usize>| {
+            x.is_some_and(|x| {
+                matches!(
+                    self.get_character(CharCoord::new(x, index.y)),
+                    Character::Text(_)
+                )
+            })
+        };
+        if is_text(index.x.checked_sub(1)) || is_text(Some(index.x + 1)) {
+            return None;
+        }
+        Some(shade.glyph(unicode))
+    }
+
+    /// Renders using the given function to turn the bitmaps of line art into
+    /// characters, and the given kind of escape codes for colors, if any.
+    /// Shades are rendered using Unicode block elements if unicode is set,
+    /// or ASCII characters otherwise.
+    fn render_with<F: Fn(CharCoord, BitmapChar) -> char>(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        color: Option<ColorMode>,
+        unicode: bool,
+        translate: F,
+    ) -> std::fmt::Result {
+        let mut line = Vec::with_capacity(self.width);
+        for y in 0..self.height() {
+            for x in 0..self.width {
+                let index = CharCoord::new(x, y);
+                let cell = self.get_style(index);
+                let c = match self.get_character(index) {
+                    Character::Text(c) => c,
+                    Character::Bitmap(l) if l.0 == 0 => self
+                        .shade_at(index, cell, color, unicode)
+                        .unwrap_or_else(|| translate(index, l)),
+                    Character::Bitmap(l) => translate(index, l),
+                };
+                line.push((c, cell));
+            }
+            self.write_line(output, color, &line)?;
             line.clear();
         }
         if !self.footnotes.is_empty() {
@@ -349,21 +1645,61 @@ impl Canvas {
         Ok(())
     }
 
+    /// Writes a single line of rendered characters along with their colors,
+    /// omitting trailing whitespace. Colors are written as ANSI escape codes
+    /// if a color mode is set. Characters without a foreground color on top
+    /// of a background color get black or white as their foreground color,
+    /// whichever is more readable.
+    fn write_line(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        color: Option<ColorMode>,
+        line: &[(char, CellStyle)],
+    ) -> std::fmt::Result {
+        let Some(mode) = color else {
+            let text: String = line.iter().map(|&(c, _)| c).collect();
+            return writeln!(output, "{}", text.trim_end());
+        };
+        let len = line
+            .iter()
+            .rposition(|&(c, color)| c != ' ' || color.background.is_some())
+            .map_or(0, |i| i + 1);
+        let mut current = (None, None);
+        for &(c, cell) in &line[..len] {
+            let foreground = match (c, cell.foreground, cell.background) {
+                (' ', _, _) => None,
+                (_, None, Some(background)) if background.is_light() => Some(Rgb::new(0, 0, 0)),
+                (_, None, Some(_)) => Some(Rgb::new(255, 255, 255)),
+                (_, foreground, _) => foreground,
+            };
+            if (foreground, cell.background) != current {
+                current = (foreground, cell.background);
+                write!(output, "{}", mode.escape(foreground, cell.background))?;
+            }
+            write!(output, "{c}")?;
+        }
+        if current != (None, None) {
+            write!(output, "\x1b[0m")?;
+        }
+        writeln!(output)
+    }
+
     /// Renders to a string at x3/x2.5 times the scale, such that one subpixel
     /// equals half a character vertically, which can be perfectly represented
     /// using box-drawing characters. Allows visualization of the complete
     /// canvas without bitmap font heuristics.
-    pub fn debug_render(&self, output: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let in_width = self.width;
-        let in_height = (self.data.len() + in_width - 1) / in_width;
+    pub fn debug_render(&self, output: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        let in_height = self.height();
         let out_width = self.width * 3;
         let out_height = in_height * 2 + (in_height + 1) / 2;
+        let left = self.origin.0 * 3;
+        let top = self.origin.1 * 5;
         let mut line = String::with_capacity(out_width);
         for y in 0..out_height {
             for x in 0..out_width {
-                let upper = PixelCoord::new(x as i64, (y * 2) as i64);
-                let lower = PixelCoord::new(x as i64, (y * 2 + 1) as i64);
-                if x % 3 == 1 && (upper.y % 5 == 2 || lower.y % 5 == 2) {
+                let upper = PixelCoord::new(left + x as i64, top + (y * 2) as i64);
+                let lower = PixelCoord::new(left + x as i64, top + (y * 2 + 1) as i64);
+                if x % 3 == 1 && (upper.y.rem_euclid(5) == 2 || lower.y.rem_euclid(5) == 2) {
                     if let Some((cc, _, _)) = self.translate_pix_to_char(upper) {
                         if let Character::Text(c) = self.get_character(cc) {
                             line.push(c);
@@ -385,6 +1721,35 @@ impl Canvas {
         }
         Ok(())
     }
+
+    /// Renders using braille patterns, see Charset::Braille. The 3x5
+    /// subpixels of each character are mapped to the dots they overlap the
+    /// most, with the middle column going to the left dots. Text characters
+    /// still take precedence.
+    fn write_braille(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        color: Option<ColorMode>,
+    ) -> std::fmt::Result {
+        self.render_with(output, color, true, |index, pixels| {
+            if let Some(' ') = Canvas::broken_stroke(self.get_style(index), pixels) {
+                return ' ';
+            }
+            let mut dots = 0u32;
+            for y in 0..5 {
+                for x in 0..3 {
+                    if pixels.peek(x, y) {
+                        dots |= BRAILLE_DOTS[BRAILLE_ROWS[y as usize]][BRAILLE_COLUMNS[x as usize]];
+                    }
+                }
+            }
+            if dots == 0 {
+                ' '
+            } else {
+                char::from_u32(0x2800 + dots).unwrap_or(' ')
+            }
+        })
+    }
 }
 
 impl std::fmt::Display for Canvas {
@@ -392,11 +1757,32 @@ impl std::fmt::Display for Canvas {
         if f.alternate() {
             self.debug_render(f)
         } else {
-            self.render(f, &BitmapFont::default())
+            self.render_to(f, &BitmapFont::default(), &RenderOptions::default())
         }
     }
 }
 
+/// Adapts an io::Write to a fmt::Write for Canvas::write_to(), keeping the
+/// first I/O error, as fmt::Error does not carry any information.
+struct IoWriter<W: std::io::Write> {
+    inner: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: std::io::Write> std::fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error.get_or_insert(e);
+            std::fmt::Error
+        })
+    }
+}
+
+/// Returns the Euclidean distance between two floating-point coordinates.
+fn distance(a: InputCoord, b: InputCoord) -> f64 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
 /// A character coordinate in an ASCII-art canvas.
 pub type CharCoord = vector2d::Vector2D<usize>;
 
@@ -405,3 +1791,355 @@ pub type PixelCoord = vector2d::Vector2D<i64>;
 
 /// A floating-point coordinate in an ASCII-art canvas.
 pub type InputCoord = vector2d::Vector2D<f64>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adapts Canvas::render(), which takes a Formatter, to Display.
+    struct Rendered<'a>(&'a dyn Fn(&mut std::fmt::Formatter<'_>) -> std::fmt::Result);
+
+    impl std::fmt::Display for Rendered<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            (self.0)(f)
+        }
+    }
+
+    fn sample() -> Canvas {
+        let mut canvas = Canvas::new(30.0, InputCoord::new(1.0, 1.0));
+        canvas.set_pen_color(Some(Rgb::new(255, 0, 0)));
+        canvas.draw_line(InputCoord::new(1.0, 2.0), InputCoord::new(28.0, 2.0));
+        canvas.draw_line(InputCoord::new(1.0, 2.0), InputCoord::new(1.0, 20.0));
+        canvas.draw_line(InputCoord::new(1.0, 20.0), InputCoord::new(28.0, 7.0));
+        canvas.draw_centered_label(InputCoord::new(15.0, 12.0), "x");
+        canvas
+    }
+
+    #[test]
+    fn renders_the_same_through_every_entry_point() {
+        let font = BitmapFont::default();
+        let canvas = sample();
+        let expected = canvas.render_to_string(&font, &RenderOptions::default());
+        assert!(!expected.contains("\x1b["));
+        assert_eq!(canvas.to_string(), expected);
+        assert_eq!(Rendered(&|f| canvas.render(f, &font)).to_string(), expected);
+
+        for charset in [
+            Charset::Ascii,
+            Charset::BoxDrawing(BoxStyle::Heavy),
+            Charset::Braille,
+        ] {
+            let options = RenderOptions {
+                charset,
+                color_mode: Some(ColorMode::Ansi256),
+            };
+            let expected = canvas.render_to_string(&font, &options);
+            assert!(expected.contains("\x1b["));
+            let mut output = String::new();
+            canvas.render_to(&mut output, &font, &options).unwrap();
+            assert_eq!(output, expected);
+            let mut written = vec![];
+            canvas.write_to(&mut written, &font, &options).unwrap();
+            assert_eq!(String::from_utf8(written).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn wraps_text_ignoring_control_characters() {
+        assert_eq!(
+            Canvas::wrap_text("ab\x01c", 3),
+            Some(vec!["ab\x01c".to_string()])
+        );
+        assert_eq!(
+            Canvas::wrap_text("a\x01b cd", 3),
+            Some(vec!["a\x01b".to_string(), "cd".to_string()])
+        );
+        assert_eq!(Canvas::wrap_text("abcd", 3), None);
+    }
+
+    /// Renders a canvas with the given character set, without colors, as a
+    /// vector of lines.
+    fn lines(canvas: &Canvas, charset: Charset) -> Vec<Vec<char>> {
+        let options = RenderOptions {
+            charset,
+            color_mode: None,
+        };
+        canvas
+            .render_to_string(&BitmapFont::default(), &options)
+            .lines()
+            .map(|line| line.chars().collect())
+            .collect()
+    }
+
+    /// Draws a horizontal line through the middle of the second row of
+    /// characters, from column 1 to 18, with the given stroke.
+    fn stroked_line(stroke: Stroke) -> Canvas {
+        let mut canvas = Canvas::new(60.0, InputCoord::new(1.0, 1.0));
+        canvas.set_stroke(stroke);
+        canvas.draw_line(InputCoord::new(3.0, 7.0), InputCoord::new(55.0, 7.0));
+        canvas
+    }
+
+    /// Returns the characters of the line drawn by stroked_line(), except
+    /// for its ends, which are drawn using the bitmap font.
+    fn stroked_chars(canvas: &Canvas, charset: Charset) -> Vec<char> {
+        lines(canvas, charset)[1][2..18].to_vec()
+    }
+
+    #[test]
+    fn alternates_dashes() {
+        let canvas = stroked_line(Stroke::Dashed);
+        let line = stroked_chars(&canvas, Charset::Ascii);
+        assert!(line.iter().all(|&c| c == '-' || c == ' '), "{line:?}");
+        assert!(
+            line.windows(2)
+                .all(|pair| (pair[0] == ' ') != (pair[1] == ' ')),
+            "{line:?}"
+        );
+    }
+
+    #[test]
+    fn draws_strokes_with_their_characters() {
+        let light = Charset::BoxDrawing(BoxStyle::Light);
+        for (stroke, ascii, box_drawing) in [
+            (Stroke::Solid, '-', '─'),
+            (Stroke::Dashed, '-', '┄'),
+            (Stroke::Dotted, '.', '┈'),
+            (Stroke::Bold, '=', '━'),
+        ] {
+            let canvas = stroked_line(stroke);
+            let line = stroked_chars(&canvas, Charset::Ascii);
+            let gap = |c: char| stroke == Stroke::Dashed && c == ' ';
+            assert!(line.iter().all(|&c| c == ascii || gap(c)), "{line:?}");
+            let line = stroked_chars(&canvas, light);
+            assert!(line.iter().all(|&c| c == box_drawing), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn draws_junctions_of_different_strokes_solid() {
+        for (first, second) in [
+            (Stroke::Dashed, Stroke::Solid),
+            (Stroke::Solid, Stroke::Dotted),
+        ] {
+            let mut canvas = stroked_line(first);
+            // A vertical line through the middle of column 5.
+            canvas.set_stroke(second);
+            canvas.draw_line(InputCoord::new(16.0, 2.0), InputCoord::new(16.0, 22.0));
+
+            let rows = lines(&canvas, Charset::BoxDrawing(BoxStyle::Light));
+            assert_eq!(rows[1][5], '┼', "{first:?} and {second:?}: {rows:?}");
+            let horizontal = if first == Stroke::Dashed {
+                '┄'
+            } else {
+                '─'
+            };
+            assert_eq!(rows[1][4], horizontal);
+            assert_eq!(rows[1][6], horizontal);
+            let vertical = if second == Stroke::Dotted {
+                '┊'
+            } else {
+                '│'
+            };
+            assert_eq!(rows[3][5], vertical);
+
+            let rows = lines(&canvas, Charset::Ascii);
+            assert_eq!(rows[1][5], '+', "{first:?} and {second:?}: {rows:?}");
+        }
+    }
+
+    #[test]
+    fn rounds_only_the_corners_of_rounded_boxes() {
+        // Corners at every position within their characters.
+        for (left, top) in [(3.0, 5.0), (4.0, 6.0), (5.0, 7.0), (5.0, 9.0)] {
+            let (right, bottom) = (left + 36.0, top + 20.0);
+            let mut canvas = Canvas::new(60.0, InputCoord::new(1.0, 1.0));
+            canvas.draw_rounded_rect(InputCoord::new(left, top), InputCoord::new(right, bottom));
+            let cell = |x: f64, y: f64| ((x / 3.0) as usize, (y / 5.0) as usize);
+            let (x0, y0) = cell(left, top);
+            let (x1, y1) = cell(right, bottom);
+
+            let rows = lines(&canvas, Charset::BoxDrawing(BoxStyle::Light));
+            let corners = [rows[y0][x0], rows[y0][x1], rows[y1][x0], rows[y1][x1]];
+            assert_eq!(corners, ['╭', '╮', '╰', '╯'], "{rows:?}");
+            assert_eq!(rows[y0][x0 + 1], '─', "{rows:?}");
+            assert_eq!(rows[y0 + 1][x0], '│', "{rows:?}");
+            assert_eq!(rows[y1][x1 - 1], '─', "{rows:?}");
+            assert_eq!(rows[y1 - 1][x1], '│', "{rows:?}");
+
+            let rows = lines(&canvas, Charset::Ascii);
+            let corners = [rows[y0][x0], rows[y0][x1], rows[y1][x0], rows[y1][x1]];
+            assert_eq!(corners, ['.', '.', '\'', '\''], "{rows:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_square_corners_next_to_rounded_ones() {
+        let mut canvas = Canvas::new(60.0, InputCoord::new(1.0, 1.0));
+        canvas.draw_rounded_rect(InputCoord::new(4.0, 7.0), InputCoord::new(22.0, 22.0));
+        canvas.draw_rect(InputCoord::new(25.0, 7.0), InputCoord::new(40.0, 22.0));
+        let rows = lines(&canvas, Charset::BoxDrawing(BoxStyle::Light));
+        assert_eq!(rows[1][7], '╮', "{rows:?}");
+        assert_eq!(rows[1][8], '┌', "{rows:?}");
+        assert_eq!(rows[4][7], '╯', "{rows:?}");
+        assert_eq!(rows[4][8], '└', "{rows:?}");
+    }
+
+    #[test]
+    fn picks_shades_by_luma() {
+        assert_eq!(Shade::for_color(Rgb::new(255, 255, 255)), Shade::Light);
+        assert_eq!(Shade::for_color(Rgb::new(255, 255, 0)), Shade::Light);
+        assert_eq!(Shade::for_color(Rgb::new(128, 128, 128)), Shade::Medium);
+        assert_eq!(Shade::for_color(Rgb::new(0, 255, 0)), Shade::Medium);
+        assert_eq!(Shade::for_color(Rgb::new(0, 0, 255)), Shade::Dark);
+        assert_eq!(Shade::for_color(Rgb::new(0, 0, 0)), Shade::Dark);
+    }
+
+    #[test]
+    fn leaves_out_shades_next_to_text() {
+        let mut canvas = Canvas::new(60.0, InputCoord::new(1.0, 1.0));
+        canvas.fill(
+            InputCoord::new(0.0, 0.0),
+            InputCoord::new(45.0, 15.0),
+            Some(Rgb::new(0, 0, 0)),
+            Some(Shade::Dark),
+            |_| true,
+        );
+        canvas.draw_centered_label(InputCoord::new(22.5, 7.5), "ab");
+
+        let rows = lines(&canvas, Charset::Ascii);
+        let row: String = rows[1][..15].iter().collect();
+        assert_eq!(row, "##### ab ######", "{rows:?}");
+        let row: String = rows[0][..15].iter().collect();
+        assert_eq!(row, "#".repeat(15));
+
+        let rows = lines(&canvas, Charset::BoxDrawing(BoxStyle::Light));
+        let row: String = rows[1][..15].iter().collect();
+        assert_eq!(row, "▓▓▓▓▓ ab ▓▓▓▓▓▓", "{rows:?}");
+    }
+
+    #[test]
+    fn sizes_graphs_to_exactly_the_given_columns() {
+        let plain = "graph 1 4.5 2\nstop\n";
+        let graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+        for columns in [1, 2, 7, 80] {
+            let mut canvas = Canvas::for_graph(&graph, Sizing::Columns(columns));
+            assert_eq!(canvas.width(), columns);
+            canvas.draw_line(InputCoord::new(4.5, 0.0), InputCoord::new(4.5, 2.0));
+            let rows = lines(&canvas, Charset::Ascii);
+            assert!(rows.iter().all(|row| row.len() == columns), "{rows:?}");
+            assert_ne!(rows[0][columns - 1], ' ', "{rows:?}");
+        }
+    }
+
+    /// Draws a horizontal line in row 2, from column 3 to 6, on a canvas
+    /// that is 21 columns wide.
+    fn short_line() -> Canvas {
+        let mut canvas = Canvas::new(60.0, InputCoord::new(1.0, 1.0));
+        canvas.draw_line(InputCoord::new(10.0, 12.0), InputCoord::new(20.0, 12.0));
+        canvas
+    }
+
+    #[test]
+    fn trims_to_the_contents() {
+        let mut canvas = short_line();
+        assert_eq!(
+            canvas.bounding_box(),
+            Some((CharCoord::new(3, 2), CharCoord::new(6, 2)))
+        );
+        canvas.trim(0);
+        assert_eq!((canvas.width(), canvas.height()), (4, 1));
+        assert_eq!(lines(&canvas, Charset::Ascii), [['-'; 4]]);
+
+        let mut canvas = short_line();
+        canvas.trim(1);
+        assert_eq!((canvas.width(), canvas.height()), (6, 3));
+        let rows = lines(&canvas, Charset::Ascii);
+        assert_eq!(rows[1], [' ', '-', '-', '-', '-']);
+        assert!(rows[0].iter().chain(&rows[2]).all(|&c| c == ' '));
+    }
+
+    #[test]
+    fn pads_beyond_the_top_left_edges_when_trimming() {
+        let mut canvas = Canvas::new(60.0, InputCoord::new(1.0, 1.0));
+        canvas.draw_line(InputCoord::new(1.0, 2.0), InputCoord::new(10.0, 2.0));
+        canvas.trim(2);
+        assert_eq!((canvas.width(), canvas.height()), (8, 5));
+        let rows = lines(&canvas, Charset::Ascii);
+        let row: String = rows[2].iter().collect();
+        assert_eq!(row, "  ----");
+        assert_eq!(
+            canvas.bounding_box(),
+            Some((CharCoord::new(2, 2), CharCoord::new(5, 2)))
+        );
+    }
+
+    #[test]
+    fn draws_in_the_same_place_after_cropping() {
+        let second = |canvas: &mut Canvas| {
+            canvas.draw_line(InputCoord::new(10.0, 17.0), InputCoord::new(20.0, 27.0));
+            canvas.draw_centered_label(InputCoord::new(25.0, 12.0), "x");
+        };
+        let mut cropped = short_line();
+        cropped.crop(CharCoord::new(2, 1), CharCoord::new(10, 5));
+        second(&mut cropped);
+        let mut expected = short_line();
+        second(&mut expected);
+        expected.crop(CharCoord::new(2, 1), CharCoord::new(10, 5));
+        assert_eq!((cropped.width(), cropped.height()), (9, 5));
+        assert_eq!(
+            lines(&cropped, Charset::Ascii),
+            lines(&expected, Charset::Ascii)
+        );
+        assert_eq!(lines(&cropped, Charset::Ascii)[1][6], 'x');
+    }
+
+    #[test]
+    fn bounds_fills_without_line_art() {
+        let mut canvas = short_line();
+        canvas.fill(
+            InputCoord::new(30.0, 20.0),
+            InputCoord::new(45.0, 30.0),
+            Some(Rgb::new(0, 0, 255)),
+            None,
+            |_| true,
+        );
+        assert_eq!(
+            canvas.bounding_box(),
+            Some((CharCoord::new(3, 2), CharCoord::new(14, 5)))
+        );
+    }
+
+    #[test]
+    fn leaves_empty_canvases_alone() {
+        let mut canvas = Canvas::new(30.0, InputCoord::new(1.0, 1.0));
+        assert_eq!(canvas.bounding_box(), None);
+        canvas.trim(1);
+        assert_eq!((canvas.width(), canvas.height()), (11, 0));
+        assert_eq!(canvas.to_string(), "");
+    }
+
+    #[test]
+    fn keeps_labels_within_a_fixed_height() {
+        let mut canvas = Canvas::new(30.0, InputCoord::new(1.0, 1.0));
+        canvas.set_height(0.0);
+        canvas.draw_centered_label(InputCoord::new(15.0, 2.0), "ab");
+        canvas.draw_centered_label(InputCoord::new(15.0, 2.0), "cd");
+        assert_eq!(canvas.height(), 1);
+        assert_eq!(canvas.footnotes, ["cd"]);
+    }
+
+    #[test]
+    fn deserializes_fonts() {
+        let font = BitmapFont::builtin("unicode-light").unwrap();
+        let data = font.serialize();
+        let loaded = BitmapFont::try_deserialize(&format!("{data}\n")).unwrap();
+        assert_eq!(loaded.serialize(), data);
+        assert_eq!(BitmapFont::deserialize(&data).serialize(), data);
+
+        let short: String = data.chars().skip(1).collect();
+        assert!(BitmapFont::try_deserialize(&short).is_err());
+        let control = data.replacen(font.translate(BitmapChar(0)), "\t", 1);
+        assert!(BitmapFont::try_deserialize(&control).is_err());
+    }
+}
@@ -0,0 +1,509 @@
+use std::sync::OnceLock;
+
+/// A color as red, green and blue components.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// The X11 color names that graphviz uses by default, as lines of a
+/// lowercase name without spaces followed by the color in #rrggbb notation,
+/// sorted by name. Generated from the rgb.txt file that comes with X11,
+/// plus the names graphviz adds to it, such as crimson and indigo, and the
+/// newer X.org names, such as teal and the web and x11 variants of colors
+/// whose X11 and CSS definitions differ.
+const X11_COLORS: &str = include_str!("x11-colors.txt");
+
+/// Returns X11_COLORS as a table of names and colors, sorted by name. The
+/// table is parsed on first use.
+fn x11_colors() -> &'static [(&'static str, Rgb)] {
+    static TABLE: OnceLock<Vec<(&'static str, Rgb)>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        X11_COLORS
+            .lines()
+            .filter_map(|line| {
+                let (name, color) = line.split_once(' ')?;
+                match Color::parse(color) {
+                    Ok(Color::Hex { rgb, .. }) => Some((name, rgb)),
+                    _ => None,
+                }
+            })
+            .collect()
+    })
+}
+
+/// The 16 colors of the standard ANSI palette, as defined by xterm. The
+/// index is the offset from the escape code for the first color (30 for
+/// foreground or 40 for background), plus 60 - 8 for the bright colors.
+const ANSI_16: [Rgb; 16] = [
+    Rgb::new(0, 0, 0),
+    Rgb::new(205, 0, 0),
+    Rgb::new(0, 205, 0),
+    Rgb::new(205, 205, 0),
+    Rgb::new(0, 0, 238),
+    Rgb::new(205, 0, 205),
+    Rgb::new(0, 205, 205),
+    Rgb::new(229, 229, 229),
+    Rgb::new(127, 127, 127),
+    Rgb::new(255, 0, 0),
+    Rgb::new(0, 255, 0),
+    Rgb::new(255, 255, 0),
+    Rgb::new(92, 92, 255),
+    Rgb::new(255, 0, 255),
+    Rgb::new(0, 255, 255),
+    Rgb::new(255, 255, 255),
+];
+
+/// The levels of each component in the 6x6x6 color cube of the 256-color
+/// palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Rgb {
+    /// Creates a color from its components.
+    pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    /// Looks up an X11 color name, ignoring case and spaces.
+    pub fn from_name(name: &str) -> Option<Rgb> {
+        let name: String = name
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+        let table = x11_colors();
+        let index = table
+            .binary_search_by(|(entry, _)| entry.cmp(&&name[..]))
+            .ok()?;
+        Some(table[index].1)
+    }
+
+    /// Converts a color given as hue, saturation and value, each ranging
+    /// from 0 to 1.
+    pub fn from_hsv(h: f64, s: f64, v: f64) -> Rgb {
+        let (h, s, v) = (
+            h.rem_euclid(1.0) * 6.0,
+            s.clamp(0.0, 1.0),
+            v.clamp(0.0, 1.0),
+        );
+        let f = h - h.floor();
+        let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+        let (r, g, b) = match h as u8 {
+            0 => (v, t, p),
+            1 => (q, v, p),
+            2 => (p, v, t),
+            3 => (p, q, v),
+            4 => (t, p, v),
+            _ => (v, p, q),
+        };
+        let component = |c: f64| (c * 255.0).round() as u8;
+        Rgb::new(component(r), component(g), component(b))
+    }
+
+    /// Returns the squared distance to another color, weighted roughly
+    /// according to how sensitive the eye is to each component.
+    fn distance(self, other: Rgb) -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        2 * d(self.r, other.r) + 4 * d(self.g, other.g) + 3 * d(self.b, other.b)
+    }
+
+    /// Returns whether the color is closer to white than to black, such
+    /// that black text is more readable on top of it than white text.
+    pub fn is_light(self) -> bool {
+        299 * self.r as u32 + 587 * self.g as u32 + 114 * self.b as u32 > 127_500
+    }
+
+    /// Returns the index of the closest color in the 16-color ANSI palette.
+    fn to_ansi_16(self) -> u8 {
+        (0..16u8)
+            .min_by_key(|&i| self.distance(ANSI_16[i as usize]))
+            .unwrap_or(0)
+    }
+
+    /// Returns the index of the closest color in the 256-color palette,
+    /// considering only the color cube and the grayscale ramp, as the first
+    /// 16 colors differ between terminals.
+    fn to_ansi_256(self) -> u8 {
+        let level = |c: u8| {
+            (0..6)
+                .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+                .unwrap_or(0)
+        };
+        let (r, g, b) = (level(self.r), level(self.g), level(self.b));
+        let cube = Rgb::new(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+        let average = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+        let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+        let gray_level = 8 + 10 * gray_index;
+        let gray = Rgb::new(gray_level, gray_level, gray_level);
+        if self.distance(gray) < self.distance(cube) {
+            232 + gray_index
+        } else {
+            16 + 36 * r as u8 + 6 * g as u8 + b as u8
+        }
+    }
+}
+
+/// A color as graphviz accepts it for attributes such as color and
+/// fillcolor. Parsing a color and writing it back using Display yields the
+/// text as it was written, apart from surrounding whitespace, as colors keep
+/// their original spelling.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Color {
+    /// A color name, such as red or LightGrey, optionally qualified with a
+    /// color scheme, as in /x11/red or /blues9/3. An empty scheme, as in
+    /// //red, stands for the default scheme. Names are kept as written, and
+    /// need not be known.
+    Named {
+        scheme: Option<String>,
+        name: String,
+    },
+
+    /// A color in #rrggbb notation, or #rrggbbaa if it has an alpha channel.
+    /// The text is the color as written, including the #.
+    Hex {
+        rgb: Rgb,
+        alpha: Option<u8>,
+        text: String,
+    },
+
+    /// A color given as hue, saturation and value, each ranging from 0 to 1,
+    /// as in 0.1 0.5 0.9. Commas may also separate the components. The text
+    /// is the color as written.
+    Hsv {
+        h: f64,
+        s: f64,
+        v: f64,
+        text: String,
+    },
+
+    /// A list of colors separated by colons, each with an optional weight
+    /// after a semicolon, as in red;0.3:blue. graphviz uses these for
+    /// gradients, striped and wedged fills, and parallel edges. The text is
+    /// the list as written.
+    List {
+        colors: Vec<(Color, Option<f64>)>,
+        text: String,
+    },
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::Named {
+            scheme: None,
+            name: "black".to_string(),
+        }
+    }
+}
+
+impl Color {
+    /// Parses a color, throwing a reasonable error message on failure.
+    pub fn parse(text: &str) -> Result<Color, String> {
+        let text = text.trim();
+        if text.contains([':', ';']) {
+            return text
+                .split(':')
+                .map(|item| match item.split_once(';') {
+                    Some((color, weight)) => Ok((
+                        Color::parse_single(color)?,
+                        Some(weight.trim().parse().map_err(|e| {
+                            format!("failed to parse color weight ('{weight}'): {e}")
+                        })?),
+                    )),
+                    None => Ok((Color::parse_single(item)?, None)),
+                })
+                .collect::<Result<_, _>>()
+                .map(|colors| Color::List {
+                    colors,
+                    text: text.to_string(),
+                });
+        }
+        Color::parse_single(text)
+    }
+
+    /// Parses a color the way graphviz loads attributes: a color that cannot
+    /// be parsed is not an error, but is kept verbatim as a name, which
+    /// to_rgb() does not resolve and Display writes back as it was.
+    pub fn parse_lenient(text: &str) -> Color {
+        Color::parse(text).unwrap_or_else(|_| Color::Named {
+            scheme: None,
+            name: text.to_string(),
+        })
+    }
+
+    /// Parses a single color, i.e. anything but a list.
+    fn parse_single(text: &str) -> Result<Color, String> {
+        let text = text.trim();
+        if let Some(hex) = text.strip_prefix('#') {
+            let component = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+                    .ok_or_else(|| format!("invalid hexadecimal color ('{text}')"))
+            };
+            let rgb = Rgb::new(component(0)?, component(2)?, component(4)?);
+            let text = text.to_string();
+            return match hex.len() {
+                6 => Ok(Color::Hex {
+                    rgb,
+                    alpha: None,
+                    text,
+                }),
+                8 => Ok(Color::Hex {
+                    rgb,
+                    alpha: Some(component(6)?),
+                    text,
+                }),
+                _ => Err(format!("invalid hexadecimal color ('{text}')")),
+            };
+        }
+        if text.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            let components = text
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|c| !c.is_empty())
+                .map(|c| c.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("failed to parse HSV color ('{text}'): {e}"))?;
+            return match components[..] {
+                [h, s, v] => Ok(Color::Hsv {
+                    h,
+                    s,
+                    v,
+                    text: text.to_string(),
+                }),
+                _ => Err(format!("expected 3 components for HSV color ('{text}')")),
+            };
+        }
+        if text.is_empty() {
+            return Err("empty color".to_string());
+        }
+        if let Some(rest) = text.strip_prefix('/') {
+            if let Some((scheme, name)) = rest.split_once('/') {
+                return Ok(Color::Named {
+                    scheme: Some(scheme.to_string()),
+                    name: name.to_string(),
+                });
+            }
+        }
+        Ok(Color::Named {
+            scheme: None,
+            name: text.to_string(),
+        })
+    }
+
+    /// Returns the first color of a list, or the color itself otherwise.
+    pub fn first(&self) -> &Color {
+        match self {
+            Color::List { colors, .. } => colors.first().map_or(self, |(color, _)| color.first()),
+            _ => self,
+        }
+    }
+
+    /// Returns whether the color is fully transparent, i.e. whether anything
+    /// drawn in it is invisible. For lists, the first color counts.
+    pub fn is_transparent(&self) -> bool {
+        match self.first() {
+            Color::Named { name, .. } => {
+                name.eq_ignore_ascii_case("transparent") || name.eq_ignore_ascii_case("none")
+            }
+            Color::Hex { alpha, .. } => *alpha == Some(0),
+            _ => false,
+        }
+    }
+
+    /// Resolves the color to its red, green and blue components, ignoring
+    /// any alpha channel. For lists, the first color is used. Returns None
+    /// for transparent colors and names that are not known, which includes
+    /// all names outside the X11 scheme.
+    pub fn to_rgb(&self) -> Option<Rgb> {
+        if self.is_transparent() {
+            return None;
+        }
+        match self.first() {
+            Color::Named { scheme, name } => match scheme.as_deref() {
+                None | Some("") | Some("x11") | Some("X11") => Rgb::from_name(name),
+                Some(_) => None,
+            },
+            Color::Hex { rgb, .. } => Some(*rgb),
+            Color::Hsv { h, s, v, .. } => Some(Rgb::from_hsv(*h, *s, *v)),
+            Color::List { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Color::Named {
+                scheme: Some(scheme),
+                name,
+            } => write!(f, "/{scheme}/{name}"),
+            Color::Named { scheme: None, name } => write!(f, "{name}"),
+            Color::Hex { text, .. } | Color::Hsv { text, .. } | Color::List { text, .. } => {
+                write!(f, "{text}")
+            }
+        }
+    }
+}
+
+/// The set of colors that ANSI escape codes can use, depending on what the
+/// terminal supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// The 16 standard colors, supported by nearly every terminal.
+    Ansi16,
+
+    /// The 256-color xterm palette.
+    Ansi256,
+
+    /// 24-bit colors.
+    TrueColor,
+}
+
+impl ColorMode {
+    /// Returns the SGR parameters selecting the given foreground color, or
+    /// the given background color if background is set.
+    fn parameters(self, color: Rgb, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        match self {
+            ColorMode::Ansi16 => {
+                let index = color.to_ansi_16();
+                if index < 8 {
+                    format!("{}", base + index)
+                } else {
+                    format!("{}", base + 60 + index - 8)
+                }
+            }
+            ColorMode::Ansi256 => format!("{};5;{}", base + 8, color.to_ansi_256()),
+            ColorMode::TrueColor => {
+                format!("{};2;{};{};{}", base + 8, color.r, color.g, color.b)
+            }
+        }
+    }
+
+    /// Returns the escape sequence that switches to the given foreground
+    /// and background colors, starting from the default colors. None stands
+    /// for the default color of the terminal.
+    pub fn escape(self, foreground: Option<Rgb>, background: Option<Rgb>) -> String {
+        let mut parameters = vec!["0".to_string()];
+        if let Some(color) = foreground {
+            parameters.push(self.parameters(color, false));
+        }
+        if let Some(color) = background {
+            parameters.push(self.parameters(color, true));
+        }
+        format!("\x1b[{}m", parameters.join(";"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> Color {
+        let color = Color::parse(text).unwrap();
+        assert_eq!(color.to_string(), text.trim());
+        color
+    }
+
+    #[test]
+    fn round_trips_names() {
+        let color = round_trip("LightGrey");
+        assert_eq!(color.to_rgb(), Some(Rgb::new(211, 211, 211)));
+        let color = round_trip("/x11/Red");
+        assert_eq!(color.to_rgb(), Some(Rgb::new(255, 0, 0)));
+        round_trip("//red");
+        let color = round_trip("/blues9/3");
+        assert_eq!(color.to_rgb(), None);
+        round_trip("  light grey ");
+    }
+
+    #[test]
+    fn round_trips_hex_colors() {
+        let color = round_trip("#FF0000");
+        assert_eq!(color.to_rgb(), Some(Rgb::new(255, 0, 0)));
+        let color = round_trip("#aBcDeF80");
+        assert!(matches!(
+            color,
+            Color::Hex { rgb, alpha: Some(0x80), .. } if rgb == Rgb::new(0xab, 0xcd, 0xef)
+        ));
+        assert!(round_trip("#00000000").is_transparent());
+    }
+
+    #[test]
+    fn round_trips_hsv_colors() {
+        let color = round_trip("0.1,0.5,0.9");
+        assert!(matches!(color, Color::Hsv { h, s, v, .. } if (h, s, v) == (0.1, 0.5, 0.9)));
+        round_trip(".50 1 1.0");
+        let color = round_trip("0, 1,\t1");
+        assert_eq!(color.to_rgb(), Some(Rgb::new(255, 0, 0)));
+    }
+
+    #[test]
+    fn round_trips_lists() {
+        let color = round_trip("red;0.30:#0000FF");
+        match &color {
+            Color::List { colors, .. } => {
+                assert_eq!(colors.len(), 2);
+                assert_eq!(colors[0].1, Some(0.3));
+                assert_eq!(colors[1].1, None);
+            }
+            _ => panic!("expected a list, found {color:?}"),
+        }
+        assert_eq!(color.to_rgb(), Some(Rgb::new(255, 0, 0)));
+        round_trip("transparent:.1 .2 .3;.5");
+        assert!(Color::parse("none:red").unwrap().is_transparent());
+    }
+
+    #[test]
+    fn rejects_invalid_colors() {
+        assert!(Color::parse("").is_err());
+        assert!(Color::parse("#fff").is_err());
+        assert!(Color::parse("#gg0000").is_err());
+        assert!(Color::parse("0.1 0.2").is_err());
+        assert!(Color::parse("0.1 x 0.2").is_err());
+        assert!(Color::parse("red::blue").is_err());
+        assert!(Color::parse("red;x").is_err());
+    }
+
+    #[test]
+    fn keeps_invalid_colors_when_lenient() {
+        for text in ["", "#fff", "red::blue", "red;x"] {
+            let color = Color::parse_lenient(text);
+            assert_eq!(color.to_string(), text);
+            assert_eq!(color.to_rgb(), None);
+        }
+        assert_eq!(
+            Color::parse_lenient("#FF0000").to_rgb(),
+            Some(Rgb::new(255, 0, 0))
+        );
+    }
+
+    #[test]
+    fn looks_up_names() {
+        assert_eq!(Rgb::from_name("Dark Green"), Some(Rgb::new(0, 100, 0)));
+        assert_eq!(Rgb::from_name("white"), Some(Rgb::new(255, 255, 255)));
+        assert_eq!(Rgb::from_name("nosuchcolor"), None);
+        assert_eq!(Rgb::from_name("crimson"), Some(Rgb::new(220, 20, 60)));
+        assert_eq!(Rgb::from_name("indigo"), Some(Rgb::new(75, 0, 130)));
+        assert_eq!(Rgb::from_name("webgray"), Some(Rgb::new(128, 128, 128)));
+    }
+
+    #[test]
+    fn parses_the_whole_name_table() {
+        let table = x11_colors();
+        assert_eq!(table.len(), X11_COLORS.lines().count());
+        assert!(table.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn converts_to_escape_codes() {
+        let red = Some(Rgb::new(255, 0, 0));
+        assert_eq!(ColorMode::Ansi16.escape(red, None), "\x1b[0;91m");
+        assert_eq!(ColorMode::Ansi256.escape(None, red), "\x1b[0;48;5;196m");
+        assert_eq!(
+            ColorMode::TrueColor.escape(red, red),
+            "\x1b[0;38;2;255;0;0;48;2;255;0;0m"
+        );
+    }
+}
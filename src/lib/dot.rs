@@ -1,3 +1,7 @@
+use crate::color::Color;
+use crate::graph;
+use crate::json;
+use crate::xdot;
 use std::collections::HashMap;
 use std::io::BufRead;
 use utf8_chars::BufReadCharsExt;
@@ -6,12 +10,34 @@ pub trait Scalable {
     fn scale(&mut self, scale: f64);
 }
 
+/// Moving all coordinates by the same offset.
+pub trait Translatable {
+    fn translate(&mut self, offset: Coord);
+}
+
+/// Conversion between graphviz' bottom-left origin with Y pointing up and a
+/// top-left origin with Y pointing down (or vice versa).
+pub trait Flippable {
+    /// Mirrors all coordinates vertically, mapping y to height - y.
+    fn flip_y(&mut self, height: f64);
+}
+
+/// A laid-out graph. Unlike in graphviz' own output formats, the origin is
+/// the top-left corner of the graph and the Y axis points down, matching the
+/// Canvas coordinate system. The loaders convert to this automatically.
 #[derive(Clone, Debug)]
 pub struct Graph {
     pub width: f64,
     pub height: f64,
     pub nodes: HashMap<String, Node>,
     pub edges: Vec<Edge>,
+    /// The clusters of the graph, in the order graphviz lists them. Only the
+    /// JSON and xdot formats carry this information.
+    pub clusters: Vec<Cluster>,
+    /// All graph attributes by name, as far as the input format carries them.
+    pub attributes: HashMap<String, String>,
+    /// xdot drawing operations for the graph itself (background and label).
+    pub draw: Vec<xdot::Op>,
 }
 
 impl Graph {
@@ -26,6 +52,15 @@ impl Graph {
         }
     }
 
+    /// Parses a color word, throwing a reasonable error message if it is
+    /// missing. Colors that cannot be parsed are kept as written, see
+    /// Color::parse_lenient().
+    fn parse_color(words: &[String], line_no: usize, word_idx: usize) -> Result<Color, String> {
+        Ok(Color::parse_lenient(&Graph::parse_string(
+            words, line_no, word_idx,
+        )?))
+    }
+
     /// Parses a floating-point word, throwing a reasonable error message on
     /// failure.
     fn parse_float(words: &[String], line_no: usize, word_idx: usize) -> Result<f64, String> {
@@ -59,7 +94,37 @@ impl Graph {
         })
     }
 
-    /// Parses the dot plain text output format into a graph.
+    /// Parses an edge endpoint word into a node name and an optional port,
+    /// throwing a reasonable error message on failure. The plain-ext format
+    /// writes endpoints with a port as node:port, where the port may itself
+    /// be followed by :compass. Node names may contain colons as well, so
+    /// the longest prefix that names a known node is used.
+    fn parse_endpoint(
+        &self,
+        words: &[String],
+        line_no: usize,
+        word_idx: usize,
+    ) -> Result<(String, Option<String>), String> {
+        let word = Graph::parse_string(words, line_no, word_idx)?;
+        if self.nodes.contains_key(&word) {
+            return Ok((word, None));
+        }
+        for (index, _) in word.rmatch_indices(':') {
+            if self.nodes.contains_key(&word[..index]) {
+                return Ok((
+                    word[..index].to_string(),
+                    Some(word[index + 1..].to_string()),
+                ));
+            }
+        }
+        Err(format!(
+            "unknown node {word} used for edge on line {line_no}"
+        ))
+    }
+
+    /// Parses the dot plain text output format into a graph. This also
+    /// accepts the plain-ext format, which differs only in that it includes
+    /// port names in the edge statements.
     pub fn from_plain<T: BufRead>(input: &mut T) -> Result<Graph, String> {
         // Create an empty graph structure for us to populate.
         let mut graph = Graph {
@@ -67,6 +132,9 @@ impl Graph {
             height: 0.0,
             nodes: HashMap::new(),
             edges: Vec::new(),
+            clusters: Vec::new(),
+            attributes: HashMap::new(),
+            draw: Vec::new(),
         };
         let mut scale = 1.0;
 
@@ -106,26 +174,18 @@ impl Graph {
                             label: Graph::parse_string(&words, line_no, 6)?,
                             style: Graph::parse_string(&words, line_no, 7)?,
                             shape: Graph::parse_string(&words, line_no, 8)?,
-                            color: Graph::parse_string(&words, line_no, 9)?,
-                            fillcolor: Graph::parse_string(&words, line_no, 10)?,
+                            color: Graph::parse_color(&words, line_no, 9)?,
+                            fillcolor: Graph::parse_color(&words, line_no, 10)?,
+                            attributes: HashMap::new(),
+                            draw: Vec::new(),
                         };
                         if graph.nodes.insert(name.clone(), node).is_some() {
                             return Err(format!("duplicate node name {name} on line {line_no}"));
                         }
                     }
                     Some("edge") => {
-                        let tail = Graph::parse_string(&words, line_no, 1)?;
-                        if graph.nodes.get(&tail).is_none() {
-                            return Err(format!(
-                                "unknown node {tail} used for edge on line {line_no}"
-                            ));
-                        }
-                        let head = Graph::parse_string(&words, line_no, 2)?;
-                        if graph.nodes.get(&head).is_none() {
-                            return Err(format!(
-                                "unknown node {head} used for edge on line {line_no}"
-                            ));
-                        }
+                        let (tail, tail_port) = graph.parse_endpoint(&words, line_no, 1)?;
+                        let (head, head_port) = graph.parse_endpoint(&words, line_no, 2)?;
                         let num_cpts = Graph::parse_usize(&words, line_no, 3)?;
                         let cpts: Vec<Coord> = (0..num_cpts)
                             .into_iter()
@@ -143,11 +203,18 @@ impl Graph {
                         };
                         graph.edges.push(Edge {
                             tail,
+                            tail_port,
                             head,
+                            head_port,
                             cpts,
                             label,
                             style: Graph::parse_string(&words, line_no, words.len() - 2)?,
-                            color: Graph::parse_string(&words, line_no, words.len() - 1)?,
+                            color: Graph::parse_color(&words, line_no, words.len() - 1)?,
+                            dir: EdgeDir::default(),
+                            attributes: HashMap::new(),
+                            draw: Vec::new(),
+                            head_label_draw: Vec::new(),
+                            tail_label_draw: Vec::new(),
                         });
                     }
                     Some("stop") => {
@@ -191,6 +258,498 @@ impl Graph {
         // Apply the scale factor so we don't need to worry about it anymore.
         graph.scale(scale);
 
+        // Move the origin to the top-left.
+        graph.flip_y(graph.height);
+
+        Ok(graph)
+    }
+
+    /// Returns the string attributes of a JSON object, except for its name.
+    fn json_attributes(object: &json::Value) -> HashMap<String, String> {
+        object
+            .as_object()
+            .unwrap_or(&[])
+            .iter()
+            .filter(|(key, _)| key != "name")
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .collect()
+    }
+
+    /// Returns the xdot drawing operations of a JSON object, taken from the
+    /// given attributes in order. -Tjson writes these as arrays of objects,
+    /// but -Tjson0 passes them through as xdot strings if the input was
+    /// already laid out as xdot, so both are accepted.
+    fn json_draw(object: &json::Value, keys: &[&str]) -> Result<Vec<xdot::Op>, String> {
+        let mut ops = vec![];
+        for key in keys {
+            match object.get(key) {
+                Some(json::Value::Array(array)) => {
+                    for op in array {
+                        ops.push(xdot::Op::from_json(op)?);
+                    }
+                }
+                Some(json::Value::String(text)) => ops.extend(xdot::parse(text)?),
+                _ => (),
+            }
+        }
+        Ok(ops)
+    }
+
+    /// Returns the integer member with the given key of a JSON object,
+    /// throwing a reasonable error message on failure.
+    fn json_usize(object: &json::Value, key: &str, what: &str) -> Result<usize, String> {
+        match object.get(key).and_then(json::Value::as_f64) {
+            Some(x) if x >= 0.0 && x.fract() == 0.0 => Ok(x as usize),
+            Some(x) => Err(format!("invalid {key} for {what} ('{x}')")),
+            None => Err(format!("missing {key} for {what}")),
+        }
+    }
+
+    /// Parses a comma-separated list of floating-point numbers as used by
+    /// graphviz for positions and bounding boxes, throwing a reasonable error
+    /// message on failure.
+    fn json_floats(value: &str, key: &str, what: &str) -> Result<Vec<f64>, String> {
+        value
+            .split(',')
+            .map(|x| {
+                x.trim().parse().map_err(|e| {
+                    format!("failed to parse {key} for {what} as float ('{value}'): {e}")
+                })
+            })
+            .collect()
+    }
+
+    /// Parses a comma-separated coordinate pair, throwing a reasonable error
+    /// message on failure.
+    fn json_coord(value: &str, key: &str, what: &str) -> Result<Coord, String> {
+        match Graph::json_floats(value, key, what)?[..] {
+            [x, y] => Ok(Coord::new(x, y)),
+            _ => Err(format!(
+                "expected two numbers for {key} of {what} ('{value}')"
+            )),
+        }
+    }
+
+    /// Parses an edge pos attribute into its control points. Arrowhead
+    /// endpoints (the s, and e, prefixed points) are skipped, as arrowheads
+    /// are drawn up to the node outline anyway.
+    fn json_cpts(value: &str, what: &str) -> Result<Vec<Coord>, String> {
+        // Multiple splines are separated by semicolons, of which only the
+        // first is used; the others are for concentrated edges.
+        let spline = value.split(';').next().unwrap_or("");
+        spline
+            .split_whitespace()
+            .filter(|point| !point.starts_with("s,") && !point.starts_with("e,"))
+            .map(|point| Graph::json_coord(point, "pos", what))
+            .collect()
+    }
+
+    /// Expands the escape sequences graphviz supports in labels. \N, \G,
+    /// \E, \T and \H are replaced using the given substitutions, and \n, \l
+    /// and \r become line breaks. A trailing line break is dropped.
+    pub(crate) fn expand_label(label: &str, substitutions: &[(char, &str)]) -> String {
+        let mut text = String::new();
+        let mut chars = label.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                text.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n' | 'l' | 'r') => text.push('\n'),
+                Some(c) => match substitutions.iter().find(|(s, _)| *s == c) {
+                    Some((_, replacement)) => text.push_str(replacement),
+                    None => text.push(c),
+                },
+                None => text.push('\\'),
+            }
+        }
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        text
+    }
+
+    /// Returns the xdot drawing operations stored in the given attributes,
+    /// as written by dot -Txdot, taken from the given attributes in order.
+    fn xdot_draw(
+        attributes: &HashMap<String, String>,
+        keys: &[&str],
+    ) -> Result<Vec<xdot::Op>, String> {
+        let mut ops = vec![];
+        for key in keys {
+            if let Some(text) = attributes.get(*key) {
+                ops.extend(xdot::parse(text)?);
+            }
+        }
+        Ok(ops)
+    }
+
+    /// Returns the width and height of a laid-out graph from its bb
+    /// attribute, in points.
+    fn graph_size(attributes: &HashMap<String, String>) -> Result<(f64, f64), String> {
+        let bb = attributes
+            .get("bb")
+            .ok_or_else(|| "missing bb for graph; was the graph laid out?".to_string())?;
+        match Graph::json_floats(bb, "bb", "graph")?[..] {
+            [_, _, urx, ury] => Ok((urx, ury)),
+            _ => Err(format!("expected four numbers for bb of graph ('{bb}')")),
+        }
+    }
+
+    /// Creates a node from the attributes graphviz writes for it after
+    /// layout. The JSON and xdot formats store the drawing operations
+    /// differently, so draw returns those for the given attribute names.
+    fn node_of<D>(
+        name: &str,
+        graph_name: &str,
+        attributes: &HashMap<String, String>,
+        draw: D,
+    ) -> Result<Node, String>
+    where
+        D: Fn(&[&str]) -> Result<Vec<xdot::Op>, String>,
+    {
+        let what = format!("node {name}");
+        let attribute = |key: &str, default: &str| {
+            attributes
+                .get(key)
+                .map_or(default, |value| &value[..])
+                .to_string()
+        };
+        let pos = attributes
+            .get("pos")
+            .ok_or_else(|| format!("missing pos for {what}"))?;
+        let size = Coord::new(
+            Graph::json_floats(&attribute("width", "0.75"), "width", &what)?[0],
+            Graph::json_floats(&attribute("height", "0.5"), "height", &what)?[0],
+        );
+        Ok(Node {
+            name: name.to_string(),
+            coord: Graph::json_coord(pos, "pos", &what)?,
+            // Sizes are in inches, everything else is in points.
+            size: size * 72.0,
+            label: Graph::expand_label(
+                &attribute("label", "\\N"),
+                &[('N', name), ('G', graph_name)],
+            ),
+            style: attribute("style", "solid"),
+            shape: attribute("shape", "ellipse"),
+            color: Color::parse_lenient(&attribute("color", "black")),
+            fillcolor: Color::parse_lenient(&attribute("fillcolor", "lightgrey")),
+            draw: draw(&["_draw_", "_ldraw_"])?,
+            attributes: attributes.clone(),
+        })
+    }
+
+    /// Creates a cluster from the attributes graphviz writes for it after
+    /// layout, like node_of(). Returns None for clusters without a bounding
+    /// box, which graphviz omits for empty clusters.
+    fn cluster_of<D>(
+        name: &str,
+        attributes: &HashMap<String, String>,
+        nodes: Vec<String>,
+        draw: D,
+    ) -> Result<Option<Cluster>, String>
+    where
+        D: Fn(&[&str]) -> Result<Vec<xdot::Op>, String>,
+    {
+        let what = format!("cluster {name}");
+        let Some(bb) = attributes.get("bb") else {
+            return Ok(None);
+        };
+        let (a, b) = match Graph::json_floats(bb, "bb", &what)?[..] {
+            [llx, lly, urx, ury] => (Coord::new(llx, lly), Coord::new(urx, ury)),
+            _ => return Err(format!("expected four numbers for bb of {what} ('{bb}')")),
+        };
+        let label = match (attributes.get("label"), attributes.get("lp")) {
+            (Some(label), Some(lp)) => Some(Label {
+                text: Graph::expand_label(label, &[('G', name)]),
+                coord: Graph::json_coord(lp, "lp", &what)?,
+            }),
+            _ => None,
+        };
+        Ok(Some(Cluster {
+            name: name.to_string(),
+            a,
+            b,
+            label,
+            nodes,
+            draw: draw(&["_draw_", "_ldraw_"])?,
+            attributes: attributes.clone(),
+        }))
+    }
+
+    /// Creates an edge from the attributes graphviz writes for it after
+    /// layout, like node_of().
+    fn edge_of<D>(
+        tail: String,
+        head: String,
+        directed: bool,
+        graph_name: &str,
+        what: &str,
+        attributes: &HashMap<String, String>,
+        draw: D,
+    ) -> Result<Edge, String>
+    where
+        D: Fn(&[&str]) -> Result<Vec<xdot::Op>, String>,
+    {
+        let attribute = |key: &str, default: &str| {
+            attributes
+                .get(key)
+                .map_or(default, |value| &value[..])
+                .to_string()
+        };
+        let cpts = match attributes.get("pos") {
+            Some(pos) => Graph::json_cpts(pos, what)?,
+            None => Vec::new(),
+        };
+        let label = match (attributes.get("label"), attributes.get("lp")) {
+            (Some(label), Some(lp)) => {
+                let edge_name = format!("{tail}{}{head}", if directed { "->" } else { "--" });
+                Some(Label {
+                    text: Graph::expand_label(
+                        label,
+                        &[
+                            ('E', &edge_name),
+                            ('T', &tail),
+                            ('H', &head),
+                            ('G', graph_name),
+                        ],
+                    ),
+                    coord: Graph::json_coord(lp, "lp", what)?,
+                })
+            }
+            _ => None,
+        };
+        let default_dir = if directed {
+            EdgeDir::Forward
+        } else {
+            EdgeDir::None
+        };
+        let dir = match attributes.get("dir") {
+            Some(dir) => {
+                EdgeDir::parse(dir).ok_or_else(|| format!("invalid dir for {what} ('{dir}')"))?
+            }
+            None => default_dir,
+        };
+        Ok(Edge {
+            tail_port: attributes.get("tailport").cloned(),
+            head_port: attributes.get("headport").cloned(),
+            tail,
+            head,
+            cpts,
+            label,
+            style: attribute("style", "solid"),
+            color: Color::parse_lenient(&attribute("color", "black")),
+            dir,
+            draw: draw(&["_draw_", "_ldraw_"])?,
+            head_label_draw: draw(&["_hldraw_"])?,
+            tail_label_draw: draw(&["_tldraw_"])?,
+            attributes: attributes.clone(),
+        })
+    }
+
+    /// Parses graphviz' JSON output format (dot -Tjson or -Tjson0) into a
+    /// graph. Unlike the plain format, this retains clusters and all
+    /// attributes, and for -Tjson also the xdot drawing operations.
+    pub fn from_json<T: BufRead>(input: &mut T) -> Result<Graph, String> {
+        let mut text = String::new();
+        std::io::Read::read_to_string(input, &mut text)
+            .map_err(|e| format!("read failed: {e:?}"))?;
+        let root = json::Value::parse(&text)?;
+        if root.as_object().is_none() {
+            return Err("expected a JSON object for the graph".to_string());
+        }
+
+        let name = root
+            .get("name")
+            .and_then(json::Value::as_str)
+            .unwrap_or("")
+            .to_string();
+        let directed = root
+            .get("directed")
+            .and_then(json::Value::as_bool)
+            .unwrap_or(true);
+        let attributes = Graph::json_attributes(&root);
+        let (width, height) = Graph::graph_size(&attributes)?;
+
+        let mut graph = Graph {
+            width,
+            height,
+            nodes: HashMap::new(),
+            edges: Vec::new(),
+            clusters: Vec::new(),
+            draw: Graph::json_draw(&root, &["_draw_", "_ldraw_"])?,
+            attributes,
+        };
+
+        // The objects array lists the subgraphs first, followed by the nodes.
+        // Both are numbered from zero using _gvid.
+        let objects = root
+            .get("objects")
+            .and_then(json::Value::as_array)
+            .unwrap_or(&[]);
+        let num_subgraphs = root
+            .get("_subgraph_cnt")
+            .and_then(json::Value::as_f64)
+            .unwrap_or(0.0) as usize;
+        let (subgraphs, nodes) = objects.split_at(num_subgraphs.min(objects.len()));
+
+        let mut names = HashMap::new();
+        for object in nodes {
+            let gvid = Graph::json_usize(object, "_gvid", "node")?;
+            let node_name = object
+                .get("name")
+                .and_then(json::Value::as_str)
+                .ok_or_else(|| format!("missing name for node {gvid}"))?
+                .to_string();
+            let attributes = Graph::json_attributes(object);
+            let node = Graph::node_of(&node_name, &name, &attributes, |keys| {
+                Graph::json_draw(object, keys)
+            })?;
+            names.insert(gvid, node_name.clone());
+            if graph.nodes.insert(node_name.clone(), node).is_some() {
+                return Err(format!("duplicate node name {node_name}"));
+            }
+        }
+
+        for object in subgraphs {
+            let cluster_name = object
+                .get("name")
+                .and_then(json::Value::as_str)
+                .unwrap_or("");
+            if !cluster_name.starts_with("cluster") {
+                continue;
+            }
+            let attributes = Graph::json_attributes(object);
+            let nodes = object
+                .get("nodes")
+                .and_then(json::Value::as_array)
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|gvid| names.get(&(gvid.as_f64()? as usize)).cloned())
+                .collect();
+            let cluster = Graph::cluster_of(cluster_name, &attributes, nodes, |keys| {
+                Graph::json_draw(object, keys)
+            })?;
+            graph.clusters.extend(cluster);
+        }
+
+        for object in root
+            .get("edges")
+            .and_then(json::Value::as_array)
+            .unwrap_or(&[])
+        {
+            let gvid = Graph::json_usize(object, "_gvid", "edge")?;
+            let what = format!("edge {gvid}");
+            let endpoint = |key: &str| {
+                let node = Graph::json_usize(object, key, &what)?;
+                names
+                    .get(&node)
+                    .cloned()
+                    .ok_or_else(|| format!("unknown node {node} used for {what}"))
+            };
+            let tail = endpoint("tail")?;
+            let head = endpoint("head")?;
+            let attributes = Graph::json_attributes(object);
+            graph.edges.push(Graph::edge_of(
+                tail,
+                head,
+                directed,
+                &name,
+                &what,
+                &attributes,
+                |keys| Graph::json_draw(object, keys),
+            )?);
+        }
+
+        // Convert from points to inches, matching the plain format.
+        graph.scale(1.0 / 72.0);
+
+        // Move the origin to the top-left.
+        graph.flip_y(graph.height);
+
+        Ok(graph)
+    }
+
+    /// Parses graphviz' xdot output format (dot -Txdot) into a graph. This
+    /// is DOT source with the layout and drawing operations stored in
+    /// attributes, so it carries the same information as -Tjson. The output
+    /// of dot -Tdot is accepted as well, just without drawing operations.
+    pub fn from_xdot<T: BufRead>(input: &mut T) -> Result<Graph, String> {
+        Graph::from_dot_layout(&graph::Graph::from_dot(input)?)
+    }
+
+    /// Converts a parsed DOT graph that was already laid out by graphviz,
+    /// i.e. that has pos and bb attributes, into a laid-out graph. See
+    /// from_xdot().
+    pub fn from_dot_layout(source: &graph::Graph) -> Result<Graph, String> {
+        let name = source.id.clone().unwrap_or_default();
+        let (width, height) = Graph::graph_size(&source.attributes)?;
+        let mut graph = Graph {
+            width,
+            height,
+            nodes: HashMap::new(),
+            edges: Vec::new(),
+            clusters: Vec::new(),
+            draw: Graph::xdot_draw(&source.attributes, &["_draw_", "_ldraw_"])?,
+            attributes: source.attributes.clone(),
+        };
+
+        for node in &source.nodes {
+            let draw = |keys: &[&str]| Graph::xdot_draw(&node.attributes, keys);
+            let node = Graph::node_of(&node.id, &name, &node.attributes, draw)?;
+            graph.nodes.insert(node.name.clone(), node);
+        }
+
+        // Clusters may be nested in other subgraphs, and are listed parents
+        // first like graphviz does.
+        let mut subgraphs: Vec<&graph::Subgraph> = source.subgraphs.iter().rev().collect();
+        while let Some(subgraph) = subgraphs.pop() {
+            subgraphs.extend(subgraph.subgraphs.iter().rev());
+            let Some(cluster_name) = subgraph.id.as_deref().filter(|_| subgraph.is_cluster())
+            else {
+                continue;
+            };
+            let draw = |keys: &[&str]| Graph::xdot_draw(&subgraph.attributes, keys);
+            let cluster = Graph::cluster_of(
+                cluster_name,
+                &subgraph.attributes,
+                subgraph.nodes.clone(),
+                draw,
+            )?;
+            graph.clusters.extend(cluster);
+        }
+
+        for (index, edge) in source.edges.iter().enumerate() {
+            let what = format!("edge {index}");
+            let draw = |keys: &[&str]| Graph::xdot_draw(&edge.attributes, keys);
+            let mut laid_out = Graph::edge_of(
+                edge.tail.clone(),
+                edge.head.clone(),
+                source.directed,
+                &name,
+                &what,
+                &edge.attributes,
+                draw,
+            )?;
+            // Ports given as node:port take precedence over attributes, as
+            // in graphviz.
+            if edge.tail_port.is_some() {
+                laid_out.tail_port = edge.tail_port.clone();
+            }
+            if edge.head_port.is_some() {
+                laid_out.head_port = edge.head_port.clone();
+            }
+            graph.edges.push(laid_out);
+        }
+
+        // Convert from points to inches, matching the plain format.
+        graph.scale(1.0 / 72.0);
+
+        // Move the origin to the top-left.
+        graph.flip_y(graph.height);
+
         Ok(graph)
     }
 }
@@ -205,6 +764,86 @@ impl Scalable for Graph {
         for edge in self.edges.iter_mut() {
             edge.scale(scale)
         }
+        for cluster in self.clusters.iter_mut() {
+            cluster.scale(scale)
+        }
+        for op in self.draw.iter_mut() {
+            op.scale(scale)
+        }
+    }
+}
+
+impl Flippable for Graph {
+    fn flip_y(&mut self, height: f64) {
+        for (_, node) in self.nodes.iter_mut() {
+            node.flip_y(height);
+        }
+        for edge in self.edges.iter_mut() {
+            edge.flip_y(height)
+        }
+        for cluster in self.clusters.iter_mut() {
+            cluster.flip_y(height)
+        }
+        for op in self.draw.iter_mut() {
+            op.flip_y(height)
+        }
+    }
+}
+
+/// A cluster subgraph, drawn by graphviz as a box around its nodes.
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    pub name: String,
+    /// One corner of the bounding box of the cluster.
+    pub a: Coord,
+    /// The opposite corner of the bounding box of the cluster.
+    pub b: Coord,
+    pub label: Option<Label>,
+    /// The names of the nodes within the cluster, including those in nested
+    /// clusters.
+    pub nodes: Vec<String>,
+    /// All attributes of the cluster by name.
+    pub attributes: HashMap<String, String>,
+    /// xdot drawing operations for the cluster.
+    pub draw: Vec<xdot::Op>,
+}
+
+impl Scalable for Cluster {
+    fn scale(&mut self, scale: f64) {
+        self.a.scale(scale);
+        self.b.scale(scale);
+        if let Some(label) = &mut self.label {
+            label.scale(scale)
+        }
+        for op in self.draw.iter_mut() {
+            op.scale(scale)
+        }
+    }
+}
+
+impl Translatable for Cluster {
+    fn translate(&mut self, offset: Coord) {
+        self.a.translate(offset);
+        self.b.translate(offset);
+        if let Some(label) = &mut self.label {
+            label.translate(offset)
+        }
+        for op in self.draw.iter_mut() {
+            op.translate(offset)
+        }
+    }
+}
+
+impl Flippable for Cluster {
+    fn flip_y(&mut self, height: f64) {
+        self.a.flip_y(height);
+        self.b.flip_y(height);
+        if let Some(label) = &mut self.label {
+            label.flip_y(height)
+        }
+        for op in self.draw.iter_mut() {
+            op.flip_y(height)
+        }
     }
 }
 
@@ -216,25 +855,71 @@ pub struct Node {
     pub label: String,
     pub style: String,
     pub shape: String,
-    pub color: String,
-    pub fillcolor: String,
+    pub color: Color,
+    pub fillcolor: Color,
+    /// All attributes of the node by name, as far as the input format
+    /// carries them.
+    pub attributes: HashMap<String, String>,
+    /// xdot drawing operations for the node (outline and label).
+    pub draw: Vec<xdot::Op>,
 }
 
 impl Scalable for Node {
     fn scale(&mut self, scale: f64) {
         self.coord.scale(scale);
         self.size.scale(scale);
+        for op in self.draw.iter_mut() {
+            op.scale(scale)
+        }
+    }
+}
+
+impl Translatable for Node {
+    fn translate(&mut self, offset: Coord) {
+        self.coord.translate(offset);
+        for op in self.draw.iter_mut() {
+            op.translate(offset)
+        }
+    }
+}
+
+impl Flippable for Node {
+    fn flip_y(&mut self, height: f64) {
+        self.coord.flip_y(height);
+        for op in self.draw.iter_mut() {
+            op.flip_y(height)
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Edge {
     pub tail: String,
+    /// The port on the tail node that the edge is attached to, if any. Only
+    /// the plain-ext format carries this information. Includes the compass
+    /// point, if any, as port:compass or just compass.
+    pub tail_port: Option<String>,
     pub head: String,
+    /// The port on the head node that the edge is attached to, if any.
+    pub head_port: Option<String>,
     pub cpts: Vec<Coord>,
     pub label: Option<Label>,
     pub style: String,
-    pub color: String,
+    pub color: Color,
+    /// Which ends of the edge get an arrowhead. The plain format does not
+    /// carry this information, so edges parsed from it are always forward.
+    pub dir: EdgeDir,
+    /// All attributes of the edge by name, as far as the input format
+    /// carries them.
+    pub attributes: HashMap<String, String>,
+    /// xdot drawing operations for the edge (spline and label). Those for
+    /// the arrowheads are not retained, as arrowheads are drawn as text
+    /// characters instead.
+    pub draw: Vec<xdot::Op>,
+    /// xdot drawing operations for the head and tail labels, which belong
+    /// with the head and tail nodes rather than with the edge as a whole.
+    pub head_label_draw: Vec<xdot::Op>,
+    pub tail_label_draw: Vec<xdot::Op>,
 }
 
 impl Scalable for Edge {
@@ -245,6 +930,93 @@ impl Scalable for Edge {
         if let Some(label) = &mut self.label {
             label.scale(scale)
         }
+        for op in self.draw.iter_mut() {
+            op.scale(scale)
+        }
+        for op in self.head_label_draw.iter_mut() {
+            op.scale(scale)
+        }
+        for op in self.tail_label_draw.iter_mut() {
+            op.scale(scale)
+        }
+    }
+}
+
+impl Translatable for Edge {
+    fn translate(&mut self, offset: Coord) {
+        for cpt in self.cpts.iter_mut() {
+            cpt.translate(offset);
+        }
+        if let Some(label) = &mut self.label {
+            label.translate(offset)
+        }
+        for op in self.draw.iter_mut() {
+            op.translate(offset)
+        }
+        for op in self.head_label_draw.iter_mut() {
+            op.translate(offset)
+        }
+        for op in self.tail_label_draw.iter_mut() {
+            op.translate(offset)
+        }
+    }
+}
+
+impl Flippable for Edge {
+    fn flip_y(&mut self, height: f64) {
+        for cpt in self.cpts.iter_mut() {
+            cpt.flip_y(height);
+        }
+        if let Some(label) = &mut self.label {
+            label.flip_y(height)
+        }
+        for op in self.draw.iter_mut() {
+            op.flip_y(height)
+        }
+        for op in self.head_label_draw.iter_mut() {
+            op.flip_y(height)
+        }
+        for op in self.tail_label_draw.iter_mut() {
+            op.flip_y(height)
+        }
+    }
+}
+
+/// The value of graphviz' dir attribute for edges, determining which ends
+/// of an edge get an arrowhead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EdgeDir {
+    /// Arrowhead at the head only.
+    #[default]
+    Forward,
+    /// Arrowhead at the tail only.
+    Back,
+    /// Arrowheads at both ends.
+    Both,
+    /// No arrowheads.
+    None,
+}
+
+impl EdgeDir {
+    /// Parses the value of a dir attribute.
+    pub fn parse(value: &str) -> Option<EdgeDir> {
+        match value {
+            "forward" => Some(EdgeDir::Forward),
+            "back" => Some(EdgeDir::Back),
+            "both" => Some(EdgeDir::Both),
+            "none" => Some(EdgeDir::None),
+            _ => None,
+        }
+    }
+
+    /// Returns whether an arrowhead should be drawn at the head end.
+    pub fn has_head(&self) -> bool {
+        matches!(self, EdgeDir::Forward | EdgeDir::Both)
+    }
+
+    /// Returns whether an arrowhead should be drawn at the tail end.
+    pub fn has_tail(&self) -> bool {
+        matches!(self, EdgeDir::Back | EdgeDir::Both)
     }
 }
 
@@ -260,6 +1032,18 @@ impl Scalable for Label {
     }
 }
 
+impl Flippable for Label {
+    fn flip_y(&mut self, height: f64) {
+        self.coord.flip_y(height);
+    }
+}
+
+impl Translatable for Label {
+    fn translate(&mut self, offset: Coord) {
+        self.coord.translate(offset);
+    }
+}
+
 pub type Coord = vector2d::Vector2D<f64>;
 
 impl Scalable for Coord {
@@ -268,3 +1052,154 @@ impl Scalable for Coord {
         self.y *= scale;
     }
 }
+
+impl Flippable for Coord {
+    fn flip_y(&mut self, height: f64) {
+        self.y = height - self.y;
+    }
+}
+
+impl Translatable for Coord {
+    fn translate(&mut self, offset: Coord) {
+        *self = *self + offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unparseable_colors_in_plain_input() {
+        let plain = "graph 1 2 2\n\
+            node a 1 1 0.75 0.5 a solid ellipse #fff #12\n\
+            node b 1 0.5 0.75 0.5 b solid box red::blue /blues9/3\n\
+            edge a b 4 1 1 1 0.8 1 0.7 1 0.5 solid #FF0000;x\n\
+            stop\n";
+        let graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+        assert_eq!(graph.nodes["a"].color.to_string(), "#fff");
+        assert_eq!(graph.nodes["a"].fillcolor.to_string(), "#12");
+        assert_eq!(graph.nodes["a"].color.to_rgb(), None);
+        assert_eq!(graph.nodes["b"].color.to_string(), "red::blue");
+        assert_eq!(graph.nodes["b"].fillcolor.to_string(), "/blues9/3");
+        assert_eq!(graph.edges[0].color.to_string(), "#FF0000;x");
+    }
+
+    #[test]
+    fn keeps_unparseable_colors_in_json_input() {
+        let json = r##"{
+            "bb": "0,0,100,100",
+            "objects": [
+                {"_gvid": 0, "name": "a", "pos": "50,70", "color": "#fff", "fillcolor": ""},
+                {"_gvid": 1, "name": "b", "pos": "50,30", "color": "red::blue"}
+            ],
+            "edges": [{"_gvid": 0, "tail": 0, "head": 1, "color": "#12"}]
+        }"##;
+        let graph = Graph::from_json(&mut json.as_bytes()).unwrap();
+        assert_eq!(graph.nodes["a"].color.to_string(), "#fff");
+        assert_eq!(graph.nodes["a"].fillcolor.to_string(), "");
+        assert_eq!(graph.nodes["b"].color.to_string(), "red::blue");
+        assert_eq!(graph.edges[0].color.to_string(), "#12");
+    }
+
+    /// The output of dot -Txdot for a graph with a cluster and a labeled
+    /// edge.
+    const XDOT: &str = r#"
+        digraph G {
+            graph [_draw_="c 9 -#fffffe00 C 7 -#ffffff P 4 0 0 0 152 62 152 62 0 ",
+                bb="0,0,62,152",
+                xdotversion=1.7
+            ];
+            node [label="\N"];
+            subgraph cluster_x {
+                graph [_draw_="c 7 -#000000 p 4 8 80 8 144 54 144 54 80 ",
+                    _ldraw_="F 14 11 -Times-Roman c 7 -#000000 T 31 128.8 0 9 1 -X ",
+                    bb="8,80,54,144",
+                    label=X,
+                    lp="31,132.5"
+                ];
+                a    [_draw_="c 7 -#000000 e 31 106 18 18 ",
+                    _ldraw_="F 14 11 -Times-Roman c 7 -#000000 T 31 102.3 0 7 1 -a ",
+                    height=0.5,
+                    pos="31,106",
+                    width=0.5];
+            }
+            b    [_draw_="c 7 -#000000 e 31 18 18 18 ",
+                _ldraw_="F 14 11 -Times-Roman c 7 -#000000 T 31 14.3 0 7 1 -b ",
+                height=0.5,
+                pos="31,18",
+                width=0.5];
+            a -> b    [_draw_="c 7 -#000000 B 4 31 87.7 31 76.85 31 62.8 31 50.12 ",
+                _hdraw_="S 5 -solid c 7 -#000000 C 7 -#000000 P 3 34.5 50.1 31 40.1 27.5 50.1 ",
+                _ldraw_="F 14 11 -Times-Roman c 7 -#000000 T 34.5 57.8 0 7 1 -e ",
+                label=e,
+                lp="34.5,62",
+                pos="e,31,40.1 31,87.7 31,76.85 31,62.8 31,50.12"];
+        }
+    "#;
+
+    #[test]
+    fn parses_xdot_input() {
+        let graph = Graph::from_xdot(&mut XDOT.as_bytes()).unwrap();
+        // Compares a length in inches to one in points.
+        let points = |inches: f64, points: f64| (inches * 72.0 - points).abs() < 1e-9;
+        assert!(points(graph.width, 62.0) && points(graph.height, 152.0));
+        assert!(!graph.draw.is_empty());
+
+        let a = &graph.nodes["a"];
+        assert!(points(a.coord.x, 31.0) && points(a.coord.y, 152.0 - 106.0));
+        assert_eq!(a.size, Coord::new(0.5, 0.5));
+        assert_eq!(a.label, "a");
+        assert!(a
+            .draw
+            .iter()
+            .any(|op| matches!(op, xdot::Op::Text { text, .. } if text == "a")));
+
+        let [cluster] = &graph.clusters[..] else {
+            panic!("expected one cluster: {:?}", graph.clusters);
+        };
+        assert_eq!(cluster.name, "cluster_x");
+        assert_eq!(cluster.nodes, ["a"]);
+        assert_eq!(cluster.label.as_ref().unwrap().text, "X");
+        assert_eq!(cluster.draw.len(), 5);
+
+        let [edge] = &graph.edges[..] else {
+            panic!("expected one edge: {:?}", graph.edges);
+        };
+        assert_eq!((&edge.tail[..], &edge.head[..]), ("a", "b"));
+        assert_eq!(edge.dir, EdgeDir::Forward);
+        assert_eq!(edge.cpts.len(), 4);
+        assert_eq!(edge.label.as_ref().unwrap().text, "e");
+        // The arrowhead drawing operations are not retained.
+        assert_eq!(edge.draw.len(), 5);
+    }
+
+    #[test]
+    fn renders_xdot_input() {
+        use crate::canvas::{BitmapFont, Canvas, RenderOptions, Sizing};
+        use crate::render;
+
+        let graph = Graph::from_xdot(&mut XDOT.as_bytes()).unwrap();
+        let mut canvas = Canvas::for_graph(&graph, Sizing::default());
+        render::draw_graph(&mut canvas, &graph, &render::Options::default());
+        let text = canvas.render_to_string(&BitmapFont::default(), &RenderOptions::default());
+        let rows: Vec<&str> = text.lines().collect();
+        let row_of = |label: &str| {
+            rows.iter()
+                .position(|row| {
+                    row.split_whitespace()
+                        .any(|word| word.trim_matches('|') == label)
+                })
+                .unwrap_or_else(|| panic!("missing {label}:\n{text}"))
+        };
+        assert!(row_of("X") < row_of("a"), "{text}");
+        assert!(row_of("a") < row_of("e"), "{text}");
+        assert!(row_of("e") < row_of("b"), "{text}");
+    }
+
+    #[test]
+    fn requires_a_layout_in_xdot_input() {
+        let error = Graph::from_xdot(&mut "digraph { a -> b }".as_bytes()).unwrap_err();
+        assert!(error.contains("missing bb"), "{error}");
+    }
+}
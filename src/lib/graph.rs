@@ -1 +1,1931 @@
+use crate::canvas;
+use crate::color::Color;
+use crate::dot;
+use crate::dot::Translatable;
+use crate::render;
+use std::collections::HashMap;
+use std::io::BufRead;
+use utf8_chars::BufReadCharsExt;
 
+/// A graph as described by a DOT source file, before layout. Default
+/// attributes from node and edge attribute statements are already applied
+/// to the nodes and edges they affect, so each node and edge carries the
+/// complete set of attributes that was specified for it.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    /// Whether the graph was declared strict, i.e. without multi-edges.
+    pub strict: bool,
+
+    /// Whether this is a digraph rather than a graph.
+    pub directed: bool,
+
+    /// The name of the graph, if any.
+    pub id: Option<String>,
+
+    /// Graph attributes by name.
+    pub attributes: HashMap<String, String>,
+
+    /// All nodes of the graph, including those declared in subgraphs, in
+    /// order of first appearance.
+    pub nodes: Vec<Node>,
+
+    /// All edges of the graph, including those declared in subgraphs, in
+    /// order of appearance.
+    pub edges: Vec<Edge>,
+
+    /// The subgraphs declared directly within the graph.
+    pub subgraphs: Vec<Subgraph>,
+}
+
+/// A node in a graph.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub id: String,
+
+    /// Node attributes by name.
+    pub attributes: HashMap<String, String>,
+}
+
+/// An edge in a graph.
+#[derive(Clone, Debug)]
+pub struct Edge {
+    pub tail: String,
+
+    /// The port on the tail node that the edge is attached to, if any.
+    /// Includes the compass point, if any, as port:compass or just compass.
+    pub tail_port: Option<String>,
+
+    pub head: String,
+
+    /// The port on the head node that the edge is attached to, if any.
+    pub head_port: Option<String>,
+
+    /// Edge attributes by name.
+    pub attributes: HashMap<String, String>,
+}
+
+/// A subgraph, which may be a cluster if its name starts with "cluster".
+#[derive(Clone, Debug, Default)]
+pub struct Subgraph {
+    /// The name of the subgraph, or None for anonymous subgraphs.
+    pub id: Option<String>,
+
+    /// Graph attributes specific to this subgraph by name.
+    pub attributes: HashMap<String, String>,
+
+    /// The names of the nodes within the subgraph, including those in
+    /// nested subgraphs, in order of first appearance.
+    pub nodes: Vec<String>,
+
+    /// The subgraphs declared directly within this subgraph.
+    pub subgraphs: Vec<Subgraph>,
+}
+
+impl Subgraph {
+    /// Returns whether this subgraph is a cluster, i.e. whether graphviz
+    /// would draw it as a box around its nodes.
+    pub fn is_cluster(&self) -> bool {
+        self.id.as_ref().is_some_and(|id| id.starts_with("cluster"))
+    }
+}
+
+impl Graph {
+    /// Parses a graph in the DOT language. The input must contain exactly
+    /// one graph. IDs are returned as written, except that quoted strings
+    /// have their quotes and \" escapes removed and are concatenated where +
+    /// is used. Other escape sequences, such as \n in labels, are left for
+    /// the consumer of the attribute to interpret. HTML strings retain their
+    /// outer angle brackets, to distinguish them from normal strings.
+    pub fn from_dot<T: BufRead>(input: &mut T) -> Result<Graph, String> {
+        let text = input
+            .chars()
+            .collect::<Result<String, _>>()
+            .map_err(|e| format!("read failed: {e:?}"))?;
+        let mut parser = Parser {
+            tokens: Lexer::tokenize(&text)?,
+            pos: 0,
+            graph: Graph::default(),
+            node_indices: HashMap::new(),
+        };
+        parser.parse_graph()?;
+        if parser.peek().is_some() {
+            return Err(parser.unexpected("end of input after graph"));
+        }
+        Ok(parser.graph)
+    }
+}
+
+/// A lexical token of the DOT language.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// An ID: an unquoted name, a numeral, a quoted string, or an HTML
+    /// string.
+    Id(String),
+
+    /// One of the keywords strict, graph, digraph, node, edge or subgraph,
+    /// in lowercase. Keywords are case-independent.
+    Keyword(&'static str),
+
+    /// An edge operation; -> for directed and -- for undirected graphs.
+    EdgeOp(&'static str),
+
+    /// A single-character punctuation token: { } [ ] ; , = or :.
+    Punct(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Id(id) => write!(f, "ID {id}"),
+            Token::Keyword(keyword) => write!(f, "keyword {keyword}"),
+            Token::EdgeOp(op) => write!(f, "'{op}'"),
+            Token::Punct(c) => write!(f, "'{c}'"),
+        }
+    }
+}
+
+/// Tokenizer state for the DOT language.
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line_no: usize,
+}
+
+impl Lexer {
+    /// Splits DOT source text into tokens, each paired with the line number
+    /// it starts on.
+    fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, String> {
+        let mut lexer = Lexer {
+            chars: text.chars().collect(),
+            pos: 0,
+            line_no: 1,
+        };
+        let mut tokens = vec![];
+        loop {
+            lexer.skip_whitespace()?;
+            let line_no = lexer.line_no;
+            match lexer.next_token()? {
+                Some(token) => tokens.push((token, line_no)),
+                None => return Ok(tokens),
+            }
+        }
+    }
+
+    /// Returns the character at the given offset from the current position.
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    /// Consumes the next character, keeping track of the line number.
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek(0);
+        if c.is_some() {
+            self.pos += 1;
+        }
+        if c == Some('\n') {
+            self.line_no += 1;
+        }
+        c
+    }
+
+    /// Returns whether the current position is at the start of a line,
+    /// ignoring leading whitespace.
+    fn at_line_start(&self) -> bool {
+        self.chars[..self.pos]
+            .iter()
+            .rev()
+            .take_while(|&&c| c != '\n')
+            .all(|c| c.is_whitespace())
+    }
+
+    /// Skips over whitespace and comments. Lines starting with # are treated
+    /// as comments as well, as they are C preprocessor output.
+    fn skip_whitespace(&mut self) -> Result<(), String> {
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.next();
+                }
+                (Some('/'), Some('/')) => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                }
+                (Some('#'), _) if self.at_line_start() => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let line_no = self.line_no;
+                    self.next();
+                    self.next();
+                    loop {
+                        match self.next() {
+                            Some('*') if self.peek(0) == Some('/') => {
+                                self.next();
+                                break;
+                            }
+                            Some(_) => (),
+                            None => {
+                                return Err(format!(
+                                    "unterminated comment starting on line {line_no}"
+                                ))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Lexes the next token, if there is one. Whitespace must have been
+    /// skipped already.
+    fn next_token(&mut self) -> Result<Option<Token>, String> {
+        let Some(c) = self.peek(0) else {
+            return Ok(None);
+        };
+        Ok(Some(match c {
+            '-' if matches!(self.peek(1), Some('>' | '-')) => {
+                self.next();
+                if self.next() == Some('>') {
+                    Token::EdgeOp("->")
+                } else {
+                    Token::EdgeOp("--")
+                }
+            }
+            '{' | '}' | '[' | ']' | ';' | ',' | '=' | ':' => {
+                self.next();
+                Token::Punct(c)
+            }
+            '"' => Token::Id(self.lex_quoted()?),
+            '<' => Token::Id(self.lex_html()?),
+            c if c == '-' || c == '.' || c.is_ascii_digit() => Token::Id(self.lex_numeral()?),
+            c if c == '_' || c.is_alphabetic() || !c.is_ascii() => {
+                let mut id = String::new();
+                while let Some(c) = self.peek(0) {
+                    if c == '_' || c.is_alphanumeric() || !c.is_ascii() {
+                        id.push(c);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                match &id.to_lowercase()[..] {
+                    "strict" => Token::Keyword("strict"),
+                    "graph" => Token::Keyword("graph"),
+                    "digraph" => Token::Keyword("digraph"),
+                    "node" => Token::Keyword("node"),
+                    "edge" => Token::Keyword("edge"),
+                    "subgraph" => Token::Keyword("subgraph"),
+                    _ => Token::Id(id),
+                }
+            }
+            c => {
+                return Err(format!(
+                    "unexpected character '{c}' on line {}",
+                    self.line_no
+                ))
+            }
+        }))
+    }
+
+    /// Lexes a numeral, i.e. [-]?(.[0-9]+ | [0-9]+(.[0-9]*)?).
+    fn lex_numeral(&mut self) -> Result<String, String> {
+        let mut numeral = String::new();
+        if self.peek(0) == Some('-') {
+            numeral.push('-');
+            self.next();
+        }
+        let mut seen_dot = false;
+        while let Some(c) = self.peek(0) {
+            if c.is_ascii_digit() || (c == '.' && !seen_dot) {
+                seen_dot |= c == '.';
+                numeral.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        if !numeral.chars().any(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid numeral on line {}", self.line_no));
+        }
+        Ok(numeral)
+    }
+
+    /// Lexes one or more quoted strings joined by +, returning their
+    /// concatenated contents. The only escape sequence that is resolved is
+    /// \", and backslash-newline line continuations are removed.
+    fn lex_quoted(&mut self) -> Result<String, String> {
+        let mut string = String::new();
+        loop {
+            let line_no = self.line_no;
+            self.next();
+            loop {
+                match self.next() {
+                    Some('"') => break,
+                    Some('\\') => match self.peek(0) {
+                        Some('"') => {
+                            self.next();
+                            string.push('"');
+                        }
+                        Some('\n') => {
+                            self.next();
+                        }
+                        Some('\r') if self.peek(1) == Some('\n') => {
+                            self.next();
+                            self.next();
+                        }
+                        _ => string.push('\\'),
+                    },
+                    Some(c) => string.push(c),
+                    None => return Err(format!("unterminated string starting on line {line_no}")),
+                }
+            }
+
+            // Look ahead for a + followed by another quoted string.
+            let (pos, line_no) = (self.pos, self.line_no);
+            self.skip_whitespace()?;
+            if self.peek(0) == Some('+') {
+                self.next();
+                self.skip_whitespace()?;
+                if self.peek(0) == Some('"') {
+                    continue;
+                }
+                return Err(format!(
+                    "expected quoted string after '+' on line {}",
+                    self.line_no
+                ));
+            }
+            self.pos = pos;
+            self.line_no = line_no;
+            return Ok(string);
+        }
+    }
+
+    /// Lexes an HTML string, i.e. text enclosed in balanced angle brackets.
+    /// The outer brackets are retained.
+    fn lex_html(&mut self) -> Result<String, String> {
+        let line_no = self.line_no;
+        let mut string = String::new();
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some(c) => {
+                    string.push(c);
+                    match c {
+                        '<' => depth += 1,
+                        '>' => depth -= 1,
+                        _ => (),
+                    }
+                    if depth == 0 {
+                        return Ok(string);
+                    }
+                }
+                None => {
+                    return Err(format!(
+                        "unterminated HTML string starting on line {line_no}"
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// The default attributes in effect within a graph or subgraph body.
+#[derive(Clone, Default)]
+struct Scope {
+    node: HashMap<String, String>,
+    edge: HashMap<String, String>,
+}
+
+/// The contents of a graph or subgraph body, collected while parsing it.
+#[derive(Default)]
+struct Body {
+    attributes: HashMap<String, String>,
+    nodes: Vec<String>,
+    subgraphs: Vec<Subgraph>,
+}
+
+impl Body {
+    /// Adds a node name to the body, unless it is already there.
+    fn add_node(&mut self, name: &str) {
+        if !self.nodes.iter().any(|n| n == name) {
+            self.nodes.push(name.to_string());
+        }
+    }
+}
+
+/// One side of an edge operation: either a single node with an optional
+/// port, or all nodes of a subgraph.
+enum Operand {
+    Node(String, Option<String>),
+    Subgraph(Vec<String>),
+}
+
+/// Recursive-descent parser state for the DOT language.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    graph: Graph,
+
+    /// Index into graph.nodes for each node name.
+    node_indices: HashMap<String, usize>,
+}
+
+impl Parser {
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    /// Returns the line number of the next token, or of the last token if
+    /// there is none.
+    fn line_no(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |&(_, line_no)| line_no)
+    }
+
+    /// Consumes the next token if it equals the given one.
+    fn accept(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the given token, throwing a reasonable error message if
+    /// something else is found.
+    fn expect(&mut self, token: &Token) -> Result<(), String> {
+        if self.accept(token) {
+            return Ok(());
+        }
+        Err(self.unexpected(&token.to_string()))
+    }
+
+    /// Returns an error message for when something else than what was
+    /// expected is found.
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(found) => format!(
+                "expected {expected} but found {found} on line {}",
+                self.line_no()
+            ),
+            None => format!("expected {expected} but found end of input"),
+        }
+    }
+
+    /// Consumes an ID if the next token is one.
+    fn accept_id(&mut self) -> Option<String> {
+        if let Some(Token::Id(id)) = self.peek() {
+            let id = id.clone();
+            self.pos += 1;
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Consumes an ID, throwing a reasonable error message if something else
+    /// is found.
+    fn expect_id(&mut self) -> Result<String, String> {
+        self.accept_id().ok_or_else(|| self.unexpected("ID"))
+    }
+
+    /// Parses graph : [ strict ] (graph | digraph) [ ID ] '{' stmt_list '}'.
+    fn parse_graph(&mut self) -> Result<(), String> {
+        self.graph.strict = self.accept(&Token::Keyword("strict"));
+        if self.accept(&Token::Keyword("digraph")) {
+            self.graph.directed = true;
+        } else if !self.accept(&Token::Keyword("graph")) {
+            return Err(self.unexpected("graph or digraph"));
+        }
+        self.graph.id = self.accept_id();
+        self.expect(&Token::Punct('{'))?;
+        let mut body = Body::default();
+        self.parse_stmt_list(&mut Scope::default(), &mut body)?;
+        self.graph.attributes = body.attributes;
+        self.graph.subgraphs = body.subgraphs;
+        Ok(())
+    }
+
+    /// Parses stmt_list : [ stmt [ ';' ] stmt_list ], up to and including
+    /// the closing brace of the body.
+    fn parse_stmt_list(&mut self, scope: &mut Scope, body: &mut Body) -> Result<(), String> {
+        while !self.accept(&Token::Punct('}')) {
+            self.parse_stmt(scope, body)?;
+            self.accept(&Token::Punct(';'));
+        }
+        Ok(())
+    }
+
+    /// Parses a single statement.
+    fn parse_stmt(&mut self, scope: &mut Scope, body: &mut Body) -> Result<(), String> {
+        match self.peek() {
+            Some(Token::Keyword("graph")) => {
+                self.pos += 1;
+                self.parse_attr_list(&mut body.attributes)?;
+            }
+            Some(Token::Keyword("node")) => {
+                self.pos += 1;
+                self.parse_attr_list(&mut scope.node)?;
+            }
+            Some(Token::Keyword("edge")) => {
+                self.pos += 1;
+                self.parse_attr_list(&mut scope.edge)?;
+            }
+            Some(Token::Id(_))
+                if self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::Punct('=')) =>
+            {
+                let name = self.expect_id()?;
+                self.pos += 1;
+                let value = self.expect_id()?;
+                body.attributes.insert(name, value);
+            }
+            Some(Token::Id(_) | Token::Keyword("subgraph") | Token::Punct('{')) => {
+                let operand = self.parse_operand(scope, body)?;
+                if matches!(self.peek(), Some(Token::EdgeOp(_))) {
+                    self.parse_edge_stmt(operand, scope, body)?;
+                } else if let Operand::Node(name, _) = operand {
+                    let mut attributes = HashMap::new();
+                    if self.peek() == Some(&Token::Punct('[')) {
+                        self.parse_attr_list(&mut attributes)?;
+                    }
+                    let index = self.node_indices[&name];
+                    self.graph.nodes[index].attributes.extend(attributes);
+                }
+            }
+            _ => return Err(self.unexpected("statement")),
+        }
+        Ok(())
+    }
+
+    /// Parses attr_list : '[' [ a_list ] ']' [ attr_list ], inserting the
+    /// attributes into the given map.
+    fn parse_attr_list(&mut self, attributes: &mut HashMap<String, String>) -> Result<(), String> {
+        self.expect(&Token::Punct('['))?;
+        loop {
+            while !self.accept(&Token::Punct(']')) {
+                let name = self.expect_id()?;
+                self.expect(&Token::Punct('='))?;
+                let value = self.expect_id()?;
+                attributes.insert(name, value);
+                if !self.accept(&Token::Punct(',')) {
+                    self.accept(&Token::Punct(';'));
+                }
+            }
+            if !self.accept(&Token::Punct('[')) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Parses a node ID with an optional port, or a subgraph, creating any
+    /// nodes that did not exist yet.
+    fn parse_operand(&mut self, scope: &mut Scope, body: &mut Body) -> Result<Operand, String> {
+        if let Some(name) = self.accept_id() {
+            let mut port = None;
+            if self.accept(&Token::Punct(':')) {
+                let mut text = self.expect_id()?;
+                if self.accept(&Token::Punct(':')) {
+                    text.push(':');
+                    text.push_str(&self.expect_id()?);
+                }
+                port = Some(text);
+            }
+            self.add_node(&name, scope, body);
+            return Ok(Operand::Node(name, port));
+        }
+        let nodes = self.parse_subgraph(scope, body)?;
+        Ok(Operand::Subgraph(nodes))
+    }
+
+    /// Creates a node with the default attributes in effect if it does not
+    /// exist yet, and adds it to the body being parsed.
+    fn add_node(&mut self, name: &str, scope: &Scope, body: &mut Body) {
+        if !self.node_indices.contains_key(name) {
+            self.node_indices
+                .insert(name.to_string(), self.graph.nodes.len());
+            self.graph.nodes.push(Node {
+                id: name.to_string(),
+                attributes: scope.node.clone(),
+            });
+        }
+        body.add_node(name);
+    }
+
+    /// Parses subgraph : [ subgraph [ ID ] ] '{' stmt_list '}', returning
+    /// the names of the nodes within it. Subgraphs that reuse the name of an
+    /// earlier sibling are merged into it.
+    fn parse_subgraph(&mut self, scope: &Scope, body: &mut Body) -> Result<Vec<String>, String> {
+        let mut id = None;
+        if self.accept(&Token::Keyword("subgraph")) {
+            id = self.accept_id();
+        }
+        self.expect(&Token::Punct('{'))?;
+
+        let existing = id.as_ref().and_then(|id| {
+            body.subgraphs
+                .iter()
+                .position(|s| s.id.as_ref() == Some(id))
+        });
+        let mut sub_body = match existing {
+            Some(index) => {
+                let subgraph = body.subgraphs.remove(index);
+                Body {
+                    attributes: subgraph.attributes,
+                    nodes: subgraph.nodes,
+                    subgraphs: subgraph.subgraphs,
+                }
+            }
+            None => Body::default(),
+        };
+        self.parse_stmt_list(&mut scope.clone(), &mut sub_body)?;
+
+        for name in sub_body.nodes.iter() {
+            body.add_node(name);
+        }
+        let nodes = sub_body.nodes.clone();
+        let subgraph = Subgraph {
+            id,
+            attributes: sub_body.attributes,
+            nodes: sub_body.nodes,
+            subgraphs: sub_body.subgraphs,
+        };
+        match existing {
+            Some(index) => body.subgraphs.insert(index, subgraph),
+            None => body.subgraphs.push(subgraph),
+        }
+        Ok(nodes)
+    }
+
+    /// Parses the remainder of edge_stmt : (node_id | subgraph) edgeRHS
+    /// [ attr_list ], given its first operand.
+    fn parse_edge_stmt(
+        &mut self,
+        first: Operand,
+        scope: &mut Scope,
+        body: &mut Body,
+    ) -> Result<(), String> {
+        let mut operands = vec![first];
+        while let Some(Token::EdgeOp(op)) = self.peek() {
+            let expected = if self.graph.directed { "->" } else { "--" };
+            if *op != expected {
+                return Err(format!(
+                    "edge operation {op} used in {} on line {}",
+                    if self.graph.directed {
+                        "digraph"
+                    } else {
+                        "graph"
+                    },
+                    self.line_no()
+                ));
+            }
+            self.pos += 1;
+            operands.push(self.parse_operand(scope, body)?);
+        }
+        let mut attributes = scope.edge.clone();
+        if self.peek() == Some(&Token::Punct('[')) {
+            self.parse_attr_list(&mut attributes)?;
+        }
+
+        for pair in operands.windows(2) {
+            for (tail, tail_port) in Parser::endpoints(&pair[0]) {
+                for (head, head_port) in Parser::endpoints(&pair[1]) {
+                    self.add_edge(Edge {
+                        tail: tail.clone(),
+                        tail_port: tail_port.clone(),
+                        head: head.clone(),
+                        head_port: head_port.clone(),
+                        attributes: attributes.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the nodes and ports that an edge operand stands for.
+    fn endpoints(operand: &Operand) -> Vec<(String, Option<String>)> {
+        match operand {
+            Operand::Node(name, port) => vec![(name.clone(), port.clone())],
+            Operand::Subgraph(nodes) => nodes.iter().map(|name| (name.clone(), None)).collect(),
+        }
+    }
+
+    /// Adds an edge to the graph. For strict graphs, an edge between nodes
+    /// that are already connected is merged into the existing edge instead.
+    fn add_edge(&mut self, edge: Edge) {
+        if self.graph.strict {
+            let directed = self.graph.directed;
+            let existing = self.graph.edges.iter_mut().find(|e| {
+                (e.tail == edge.tail && e.head == edge.head)
+                    || (!directed && e.tail == edge.head && e.head == edge.tail)
+            });
+            if let Some(existing) = existing {
+                existing.attributes.extend(edge.attributes);
+                return;
+            }
+        }
+        self.graph.edges.push(edge);
+    }
+}
+
+/// Space between adjacent nodes within a layer, in character cells, for
+/// vertical and horizontal layouts respectively.
+const NODE_SEP: (f64, f64) = (3.0, 1.0);
+
+/// Space between adjacent layers, in character cells, for vertical and
+/// horizontal layouts respectively. Horizontal layouts need more, as
+/// character cells are about twice as tall as they are wide.
+const RANK_SEP: (f64, f64) = (3.0, 8.0);
+
+/// Space left between the end of an edge and a node for its arrowhead, in
+/// character cells, for vertical and horizontal layouts respectively.
+const ARROW_GAP: (f64, f64) = (1.0, 1.0);
+
+/// Room taken up by a self-loop beside its node, not including its
+/// arrowhead, in character cells, for vertical and horizontal layouts
+/// respectively.
+const LOOP_SIZE: (f64, f64) = (4.0, 2.0);
+
+/// Space between the box of a cluster and the nodes within it, in character
+/// columns and rows.
+const CLUSTER_PAD: (f64, f64) = (2.0, 1.0);
+
+/// Empty space around the graph, in character cells.
+const MARGIN: f64 = 1.0;
+
+/// Number of barycenter sweeps used to reduce edge crossings.
+const ORDER_ITERATIONS: usize = 24;
+
+/// Number of sweeps used to straighten edges when assigning coordinates.
+const POSITION_ITERATIONS: usize = 8;
+
+/// Converts an HTML label, given with its outer angle brackets as the DOT
+/// parser returns it, to plain text by dropping all tags, except for <br>
+/// tags which become line breaks.
+fn strip_html(label: &str) -> String {
+    let label = label
+        .strip_prefix('<')
+        .and_then(|label| label.strip_suffix('>'))
+        .unwrap_or(label);
+    let mut text = String::new();
+    let mut tag = None;
+    for c in label.chars() {
+        match (&mut tag, c) {
+            (None, '<') => tag = Some(String::new()),
+            (None, c) => text.push(c),
+            (Some(name), '>') => {
+                if name.trim_start().to_lowercase().starts_with("br") {
+                    text.push('\n');
+                }
+                tag = None;
+            }
+            (Some(name), c) => name.push(c),
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Returns the size of a node in character cells, such that its label fits
+/// within its outline.
+fn node_size(shape: &str, label: &str) -> dot::Coord {
+    if shape == "point" {
+        return dot::Coord::new(1.0, 1.0);
+    }
+    let lines: Vec<&str> = label.split('\n').collect();
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let fraction = render::label_fraction(shape);
+
+    // Two extra cells for the characters the outline is drawn in.
+    dot::Coord::new(
+        ((width as f64 + 2.0) / fraction).ceil() + 2.0,
+        ((lines.len() as f64) / fraction).ceil() + 2.0,
+    )
+}
+
+/// Converts a graph laid out in character cells, with X in columns and Y in
+/// rows, to inches at canvas::COLUMNS_PER_INCH.
+fn cells_to_inches(graph: &mut dot::Graph) {
+    let inch = dot::Coord::new(
+        canvas::COLUMNS_PER_INCH,
+        canvas::COLUMNS_PER_INCH / canvas::CELL_ASPECT,
+    );
+    let convert = |coord: &mut dot::Coord| {
+        coord.x /= inch.x;
+        coord.y /= inch.y;
+    };
+    graph.width /= inch.x;
+    graph.height /= inch.y;
+    for node in graph.nodes.values_mut() {
+        convert(&mut node.coord);
+        convert(&mut node.size);
+    }
+    for edge in graph.edges.iter_mut() {
+        edge.cpts.iter_mut().for_each(convert);
+        if let Some(label) = &mut edge.label {
+            convert(&mut label.coord);
+        }
+    }
+    for cluster in graph.clusters.iter_mut() {
+        convert(&mut cluster.a);
+        convert(&mut cluster.b);
+        if let Some(label) = &mut cluster.label {
+            convert(&mut label.coord);
+        }
+    }
+}
+
+/// Returns the smallest box containing both given boxes, either of which
+/// may be missing.
+fn union(
+    a: Option<(dot::Coord, dot::Coord)>,
+    b: Option<(dot::Coord, dot::Coord)>,
+) -> Option<(dot::Coord, dot::Coord)> {
+    match (a, b) {
+        (Some((a0, a1)), Some((b0, b1))) => Some((
+            dot::Coord::new(a0.x.min(b0.x), a0.y.min(b0.y)),
+            dot::Coord::new(a1.x.max(b1.x), a1.y.max(b1.y)),
+        )),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Adds the clusters among the given subgraphs and their nested subgraphs
+/// to a graph whose nodes are laid out, in character cells. Each cluster
+/// gets a box around its nodes and nested clusters, with its label at the
+/// top, and is listed before its nested clusters like graphviz does.
+/// Returns the bounds of all boxes added.
+fn layout_clusters(
+    subgraphs: &[Subgraph],
+    graph: &mut dot::Graph,
+) -> Option<(dot::Coord, dot::Coord)> {
+    let mut bounds = None;
+    for subgraph in subgraphs {
+        let index = graph.clusters.len();
+        let nested = layout_clusters(&subgraph.subgraphs, graph);
+        let Some(name) = subgraph.id.as_ref().filter(|_| subgraph.is_cluster()) else {
+            bounds = union(bounds, nested);
+            continue;
+        };
+        let mut inner = nested;
+        for node in subgraph
+            .nodes
+            .iter()
+            .filter_map(|name| graph.nodes.get(name))
+        {
+            let half = node.size / 2.0;
+            inner = union(inner, Some((node.coord - half, node.coord + half)));
+        }
+        // Self-loops of the nodes are included with their labels, roughly.
+        for edge in graph
+            .edges
+            .iter()
+            .filter(|edge| edge.tail == edge.head && subgraph.nodes.contains(&edge.tail))
+        {
+            for &cpt in edge.cpts.iter() {
+                inner = union(inner, Some((cpt, cpt)));
+            }
+            if let Some(label) = &edge.label {
+                let half = dot::Coord::new(
+                    label
+                        .text
+                        .split('\n')
+                        .map(|l| l.chars().count())
+                        .max()
+                        .unwrap_or(0) as f64,
+                    label.text.split('\n').count() as f64,
+                ) / 2.0;
+                inner = union(inner, Some((label.coord - half, label.coord + half)));
+            }
+        }
+        // Like graphviz, empty clusters are left out.
+        let Some((mut a, mut b)) = inner else {
+            continue;
+        };
+        let pad = dot::Coord::new(CLUSTER_PAD.0, CLUSTER_PAD.1);
+        a = a - pad;
+        b = b + pad;
+        let label = subgraph.attributes.get("label").map(|label| {
+            let text = if label.starts_with('<') && label.ends_with('>') {
+                strip_html(label)
+            } else {
+                dot::Graph::expand_label(label, &[('G', name)])
+            };
+            let lines = text.split('\n').count() as f64;
+            let width = text
+                .split('\n')
+                .map(|l| l.chars().count())
+                .max()
+                .unwrap_or(0) as f64;
+            a.y -= lines;
+            let grow = (width + 2.0 - (b.x - a.x)).max(0.0) / 2.0;
+            a.x -= grow;
+            b.x += grow;
+            dot::Label {
+                text,
+                coord: dot::Coord::new((a.x + b.x) / 2.0, a.y + 1.0 + lines / 2.0),
+            }
+        });
+        graph.clusters.insert(
+            index,
+            dot::Cluster {
+                name: name.clone(),
+                a,
+                b,
+                label,
+                nodes: subgraph.nodes.clone(),
+                attributes: subgraph.attributes.clone(),
+                draw: vec![],
+            },
+        );
+        bounds = union(bounds, Some((a, b)));
+    }
+    bounds
+}
+
+/// Reverses edges that close a cycle, based on a depth-first search in
+/// node order. Returns for each edge whether it was reversed.
+fn remove_cycles(num_nodes: usize, edges: &[(usize, usize)]) -> Vec<bool> {
+    let mut outgoing = vec![vec![]; num_nodes];
+    for (index, &(tail, _)) in edges.iter().enumerate() {
+        outgoing[tail].push(index);
+    }
+
+    // 0 = unvisited, 1 = on the DFS stack, 2 = done.
+    let mut state = vec![0u8; num_nodes];
+    let mut reversed = vec![false; edges.len()];
+    for root in 0..num_nodes {
+        if state[root] != 0 {
+            continue;
+        }
+        state[root] = 1;
+        let mut stack = vec![(root, 0)];
+        while let Some((node, next)) = stack.pop() {
+            let Some(&edge) = outgoing[node].get(next) else {
+                state[node] = 2;
+                continue;
+            };
+            stack.push((node, next + 1));
+            let head = edges[edge].1;
+            match state[head] {
+                0 => {
+                    state[head] = 1;
+                    stack.push((head, 0));
+                }
+                1 => reversed[edge] = true,
+                _ => (),
+            }
+        }
+    }
+    reversed
+}
+
+/// Assigns each node to a layer using the longest path from any source,
+/// given acyclic edges. Sources are then moved down as far as their
+/// successors allow, to avoid needlessly long edges.
+fn assign_layers(num_nodes: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut incoming = vec![0; num_nodes];
+    let mut outgoing = vec![vec![]; num_nodes];
+    for &(tail, head) in edges {
+        incoming[head] += 1;
+        outgoing[tail].push(head);
+    }
+
+    // Kahn's algorithm, yielding a topological order.
+    let mut order: Vec<usize> = (0..num_nodes).filter(|&v| incoming[v] == 0).collect();
+    let mut remaining = incoming.clone();
+    let mut i = 0;
+    while i < order.len() {
+        for &head in outgoing[order[i]].iter() {
+            remaining[head] -= 1;
+            if remaining[head] == 0 {
+                order.push(head);
+            }
+        }
+        i += 1;
+    }
+
+    let mut layers = vec![0; num_nodes];
+    for &node in order.iter() {
+        for &head in outgoing[node].iter() {
+            layers[head] = layers[head].max(layers[node] + 1);
+        }
+    }
+    for &node in order.iter().rev() {
+        if incoming[node] == 0 {
+            if let Some(min) = outgoing[node].iter().map(|&head| layers[head]).min() {
+                layers[node] = min - 1;
+            }
+        }
+    }
+    layers
+}
+
+/// Counts the edge crossings between all pairs of adjacent layers.
+fn count_crossings(layers: &[Vec<usize>], down: &[Vec<usize>], pos: &[usize]) -> usize {
+    let mut crossings = 0;
+    for layer in layers.iter() {
+        let edges: Vec<(usize, usize)> = layer
+            .iter()
+            .flat_map(|&u| down[u].iter().map(move |&v| (pos[u], pos[v])))
+            .collect();
+        for (i, a) in edges.iter().enumerate() {
+            for b in edges[i + 1..].iter() {
+                if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                    crossings += 1;
+                }
+            }
+        }
+    }
+    crossings
+}
+
+/// Reorders the nodes within each layer to reduce edge crossings, using the
+/// barycenter heuristic with alternating downward and upward sweeps. The
+/// best ordering found is kept.
+fn order_layers(layers: &mut Vec<Vec<usize>>, up: &[Vec<usize>], down: &[Vec<usize>]) {
+    let mut pos = vec![0; up.len()];
+    let update = |layers: &[Vec<usize>], pos: &mut [usize]| {
+        for layer in layers.iter() {
+            for (i, &v) in layer.iter().enumerate() {
+                pos[v] = i;
+            }
+        }
+    };
+    update(layers, &mut pos);
+    let mut best = layers.clone();
+    let mut best_crossings = count_crossings(layers, down, &pos);
+
+    for iteration in 0..ORDER_ITERATIONS {
+        if best_crossings == 0 {
+            break;
+        }
+        let downward = iteration % 2 == 0;
+        let indices: Vec<usize> = if downward {
+            (1..layers.len()).collect()
+        } else {
+            (0..layers.len().saturating_sub(1)).rev().collect()
+        };
+        for l in indices {
+            let neighbors = if downward { up } else { down };
+            let mut keyed: Vec<(f64, usize)> = layers[l]
+                .iter()
+                .map(|&v| {
+                    let n = &neighbors[v];
+                    if n.is_empty() {
+                        (pos[v] as f64, v)
+                    } else {
+                        (
+                            n.iter().map(|&u| pos[u] as f64).sum::<f64>() / n.len() as f64,
+                            v,
+                        )
+                    }
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            layers[l] = keyed.into_iter().map(|(_, v)| v).collect();
+            for (i, &v) in layers[l].iter().enumerate() {
+                pos[v] = i;
+            }
+        }
+        let crossings = count_crossings(layers, down, &pos);
+        if crossings < best_crossings {
+            best_crossings = crossings;
+            best = layers.clone();
+        }
+    }
+    *layers = best;
+}
+
+/// Assigns positions along each layer, given the extent of each node along
+/// the layer. Nodes are repeatedly pulled towards the average position of
+/// their neighbors in the adjacent layer, while keeping their order and
+/// the given minimum separation.
+fn assign_positions(
+    layers: &[Vec<usize>],
+    up: &[Vec<usize>],
+    down: &[Vec<usize>],
+    extents: &[f64],
+    sep: f64,
+) -> Vec<f64> {
+    let mut x = vec![0.0; extents.len()];
+    for layer in layers.iter() {
+        let mut right = 0.0;
+        for &v in layer.iter() {
+            x[v] = right + extents[v] / 2.0;
+            right += extents[v] + sep;
+        }
+    }
+
+    for iteration in 0..POSITION_ITERATIONS * 2 {
+        let downward = iteration % 2 == 0;
+        let neighbors = if downward { up } else { down };
+        let indices: Vec<usize> = if downward {
+            (0..layers.len()).collect()
+        } else {
+            (0..layers.len()).rev().collect()
+        };
+        for l in indices {
+            let layer = &layers[l];
+            let desired: Vec<f64> = layer
+                .iter()
+                .map(|&v| {
+                    let n = &neighbors[v];
+                    if n.is_empty() {
+                        x[v]
+                    } else {
+                        n.iter().map(|&u| x[u]).sum::<f64>() / n.len() as f64
+                    }
+                })
+                .collect();
+            let gap = |i: usize| (extents[layer[i - 1]] + extents[layer[i]]) / 2.0 + sep;
+
+            // Both passes yield valid placements that are as close to the
+            // desired positions as possible while being biased in opposite
+            // directions, so their average is valid and unbiased.
+            let mut left = desired.clone();
+            for i in 1..layer.len() {
+                left[i] = left[i].max(left[i - 1] + gap(i));
+            }
+            let mut right = desired;
+            for i in (1..layer.len()).rev() {
+                right[i - 1] = right[i - 1].min(right[i] - gap(i));
+            }
+            for (i, &v) in layer.iter().enumerate() {
+                x[v] = (left[i] + right[i]) / 2.0;
+            }
+        }
+    }
+    x
+}
+
+/// Returns where edges attach to the sides of the nodes, as offsets from
+/// the node center along the layer for the start and end of each chain of
+/// nodes. Edges are spread out over the middle half of each side in the
+/// order of the nodes they lead to, such that they do not all converge on a
+/// single point.
+fn spread_edges(chains: &[Vec<usize>], x: &[f64], along: &[f64]) -> Vec<(f64, f64)> {
+    // (chain index, whether it is the end of the chain, neighbor position)
+    let mut sides: HashMap<(usize, bool), Vec<(usize, f64)>> = HashMap::new();
+    for (index, chain) in chains.iter().enumerate() {
+        let n = chain.len();
+        sides
+            .entry((chain[0], false))
+            .or_default()
+            .push((index, x[chain[1]]));
+        sides
+            .entry((chain[n - 1], true))
+            .or_default()
+            .push((index, x[chain[n - 2]]));
+    }
+    let mut offsets = vec![(0.0, 0.0); chains.len()];
+    for ((node, is_end), mut edges) in sides {
+        edges.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let count = edges.len() as f64;
+        for (i, (index, _)) in edges.into_iter().enumerate() {
+            let offset = along[node] / 2.0 * ((i as f64 + 0.5) / count - 0.5);
+            if is_end {
+                offsets[index].1 = offset;
+            } else {
+                offsets[index].0 = offset;
+            }
+        }
+    }
+    offsets
+}
+
+/// Returns the distance from the center of a node to its outline across
+/// the layers, at the given offset from the center along the layer. The
+/// extents of the node are given as (along layer, across layers).
+fn side_depth(shape: &str, extents: (f64, f64), offset: f64) -> f64 {
+    let t = (offset * 2.0 / extents.0).abs().min(1.0);
+    let half = extents.1 / 2.0;
+    match shape {
+        "ellipse" | "oval" | "circle" | "doublecircle" | "egg" => half * (1.0 - t * t).sqrt(),
+        "diamond" => half * (1.0 - t),
+        _ => half,
+    }
+}
+
+impl Graph {
+    /// Lays out the graph using a layered (Sugiyama-style) algorithm, in
+    /// the spirit of graphviz' dot: cycles are broken by reversing edges,
+    /// nodes are assigned to layers by longest path, the order within each
+    /// layer is chosen to reduce edge crossings, and finally coordinates are
+    /// assigned such that edges are as straight as possible.
+    ///
+    /// The layout is done in character cells, but the result is converted
+    /// to inches like graphviz' output, at canvas::COLUMNS_PER_INCH, such
+    /// that it is rendered cell for cell by a Canvas::for_graph() with the
+    /// default sizing. Edges are routed through the layers they span as
+    /// piecewise Bézier curves. The rankdir graph attribute is respected.
+    /// Self-loops are drawn beside their node. Clusters do not affect the
+    /// layout; their boxes are drawn around their nodes afterwards, and may
+    /// therefore overlap other nodes.
+    pub fn layout(&self) -> dot::Graph {
+        let rankdir = self
+            .attributes
+            .get("rankdir")
+            .map_or("TB".to_string(), |dir| dir.to_uppercase());
+        let horizontal = rankdir == "LR" || rankdir == "RL";
+        let (node_sep, rank_sep, arrow_gap, loop_size) = if horizontal {
+            (NODE_SEP.1, RANK_SEP.1, ARROW_GAP.1, LOOP_SIZE.1)
+        } else {
+            (NODE_SEP.0, RANK_SEP.0, ARROW_GAP.0, LOOP_SIZE.0)
+        };
+
+        // Resolve node labels and sizes. Extents are given as (along layer,
+        // across layers).
+        let graph_name = self.id.clone().unwrap_or_default();
+        let edge_label = |edge: &Edge| {
+            edge.attributes.get("label").map(|label| {
+                if label.starts_with('<') && label.ends_with('>') {
+                    strip_html(label)
+                } else {
+                    let op = if self.directed { "->" } else { "--" };
+                    let name = format!("{}{op}{}", edge.tail, edge.head);
+                    dot::Graph::expand_label(
+                        label,
+                        &[
+                            ('E', &name),
+                            ('T', &edge.tail),
+                            ('H', &edge.head),
+                            ('G', &graph_name),
+                        ],
+                    )
+                }
+            })
+        };
+        let label_width = |text: &str| {
+            text.split('\n')
+                .map(|l| l.chars().count())
+                .max()
+                .unwrap_or(0) as f64
+        };
+        let mut nodes = vec![];
+        for node in self.nodes.iter() {
+            let attribute = |key: &str, default: &str| {
+                node.attributes
+                    .get(key)
+                    .map_or(default, |value| &value[..])
+                    .to_string()
+            };
+            let shape = attribute("shape", "ellipse");
+            let label = attribute("label", "\\N");
+            let label = if label.starts_with('<') && label.ends_with('>') {
+                strip_html(&label)
+            } else {
+                dot::Graph::expand_label(&label, &[('N', &node.id), ('G', &graph_name)])
+            };
+            let label = if shape == "point" {
+                String::new()
+            } else {
+                label
+            };
+            let size = node_size(&shape, &label);
+            nodes.push(dot::Node {
+                name: node.id.clone(),
+                coord: dot::Coord::new(0.0, 0.0),
+                size,
+                label,
+                style: attribute("style", "solid"),
+                shape,
+                color: Color::parse_lenient(&attribute("color", "black")),
+                fillcolor: Color::parse_lenient(&attribute("fillcolor", "lightgrey")),
+                attributes: node.attributes.clone(),
+                draw: vec![],
+            });
+        }
+        let extent = |size: dot::Coord| {
+            if horizontal {
+                (size.y, size.x)
+            } else {
+                (size.x, size.y)
+            }
+        };
+
+        // Make the graph acyclic and assign layers.
+        let index: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (&node.id[..], i))
+            .collect();
+        let links: Vec<(usize, usize, &Edge)> = self
+            .edges
+            .iter()
+            .map(|edge| (index[&edge.tail[..]], index[&edge.head[..]], edge))
+            .filter(|&(tail, head, _)| tail != head)
+            .collect();
+        let pairs: Vec<(usize, usize)> = links.iter().map(|&(t, h, _)| (t, h)).collect();
+        let reversed = remove_cycles(nodes.len(), &pairs);
+        let oriented: Vec<(usize, usize)> = pairs
+            .iter()
+            .zip(reversed.iter())
+            .map(|(&(t, h), &r)| if r { (h, t) } else { (t, h) })
+            .collect();
+        let mut layer_of = assign_layers(nodes.len(), &oriented);
+
+        // Split edges that span multiple layers using virtual nodes, such
+        // that all edges connect adjacent layers.
+        let mut extents: Vec<(f64, f64)> = nodes.iter().map(|node| extent(node.size)).collect();
+        let mut up = vec![vec![]; nodes.len()];
+        let mut down = vec![vec![]; nodes.len()];
+        let mut chains = vec![];
+        for &(tail, head) in oriented.iter() {
+            let mut chain = vec![tail];
+            for layer in layer_of[tail] + 1..layer_of[head] {
+                chain.push(extents.len());
+                extents.push((0.0, 0.0));
+                layer_of.push(layer);
+                up.push(vec![]);
+                down.push(vec![]);
+            }
+            chain.push(head);
+            for pair in chain.windows(2) {
+                down[pair[0]].push(pair[1]);
+                up[pair[1]].push(pair[0]);
+            }
+            chains.push(chain);
+        }
+        let num_layers = layer_of.iter().map(|&l| l + 1).max().unwrap_or(0);
+        let mut layers = vec![vec![]; num_layers];
+        for (v, &layer) in layer_of.iter().enumerate() {
+            layers[layer].push(v);
+        }
+
+        // Self-loops are drawn beside their node, after it along the layer,
+        // with their label beyond them. Nodes with self-loops are positioned
+        // as if they took up that much more room on both sides, such that
+        // they stay centered on the rest of their edges.
+        let self_loops: Vec<(usize, &Edge)> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.tail == edge.head)
+            .map(|edge| (index[&edge.tail[..]], edge))
+            .collect();
+        let label_extent = |text: &str| {
+            if horizontal {
+                text.split('\n').count() as f64
+            } else {
+                label_width(text)
+            }
+        };
+        let mut loop_room = vec![0.0; extents.len()];
+        for &(v, edge) in self_loops.iter() {
+            let label = edge_label(edge).map_or(0.0, |text| label_extent(&text) + 1.0);
+            loop_room[v] = f64::max(loop_room[v], arrow_gap + loop_size + label);
+        }
+
+        // Order the layers and assign coordinates.
+        order_layers(&mut layers, &up, &down);
+        let along: Vec<f64> = extents.iter().map(|e| e.0).collect();
+        let room: Vec<f64> = (0..along.len())
+            .map(|v| along[v] + 2.0 * loop_room[v])
+            .collect();
+        let mut x = assign_positions(&layers, &up, &down, &room, node_sep);
+        let min = (0..x.len())
+            .map(|v| x[v] - along[v] / 2.0)
+            .fold(f64::INFINITY, f64::min);
+        for (v, x) in x.iter_mut().enumerate() {
+            // Snap to the character grid, such that outlines line up with
+            // character cells.
+            let left = (*x - along[v] / 2.0 - min + MARGIN).round();
+            *x = left + along[v] / 2.0;
+        }
+        let mut layer_top = vec![MARGIN; num_layers + 1];
+        for (l, layer) in layers.iter().enumerate() {
+            let depth = layer.iter().map(|&v| extents[v].1).fold(0.0, f64::max);
+            layer_top[l + 1] = layer_top[l] + depth + rank_sep;
+        }
+        let layer_depth = |l: usize| layer_top[l + 1] - layer_top[l] - rank_sep;
+        // Empty graphs still get their margins, rather than a negative
+        // depth from the rank separation that no layer follows.
+        let breadth = ((0..x.len())
+            .map(|v| x[v] + along[v] / 2.0 + loop_room[v])
+            .fold(0.0, f64::max)
+            + MARGIN)
+            .max(2.0 * MARGIN);
+        let depth = (layer_top[num_layers] - rank_sep + MARGIN).max(2.0 * MARGIN);
+
+        // Maps (along layer, across layers) back to (X, Y).
+        let transform = |a: f64, d: f64| match &rankdir[..] {
+            "BT" => dot::Coord::new(a, depth - d),
+            "LR" => dot::Coord::new(d, a),
+            "RL" => dot::Coord::new(depth - d, a),
+            _ => dot::Coord::new(a, d),
+        };
+        let center = |v: usize| layer_top[layer_of[v]] + layer_depth(layer_of[v]) / 2.0;
+        for (v, node) in nodes.iter_mut().enumerate() {
+            node.coord = transform(x[v], center(v));
+        }
+
+        let (width, height) = if horizontal {
+            (depth, breadth)
+        } else {
+            (breadth, depth)
+        };
+        let mut graph = dot::Graph {
+            width,
+            height,
+            nodes: HashMap::new(),
+            edges: vec![],
+            clusters: vec![],
+            attributes: self.attributes.clone(),
+            draw: vec![],
+        };
+
+        // Route the edges through the virtual nodes, from the far side of
+        // the tail node to the near side of the head node. Like graphviz,
+        // edges end a little before nodes that get an arrowhead, leaving room
+        // for it.
+        let default_dir = if self.directed {
+            dot::EdgeDir::Forward
+        } else {
+            dot::EdgeDir::None
+        };
+        let dir_of = |edge: &Edge| {
+            edge.attributes
+                .get("dir")
+                .and_then(|dir| dot::EdgeDir::parse(dir))
+                .unwrap_or(default_dir)
+        };
+        let gap = |arrow: bool| if arrow { arrow_gap } else { 0.0 };
+        let laid_out = |edge: &Edge, dir, cpts, label| {
+            let attribute = |key: &str, default: &str| {
+                edge.attributes
+                    .get(key)
+                    .map_or(default, |value| &value[..])
+                    .to_string()
+            };
+            dot::Edge {
+                tail: edge.tail.clone(),
+                tail_port: edge.tail_port.clone(),
+                head: edge.head.clone(),
+                head_port: edge.head_port.clone(),
+                cpts,
+                label,
+                style: attribute("style", "solid"),
+                color: Color::parse_lenient(&attribute("color", "black")),
+                dir,
+                attributes: edge.attributes.clone(),
+                draw: vec![],
+                head_label_draw: vec![],
+                tail_label_draw: vec![],
+            }
+        };
+        let offsets = spread_edges(&chains, &x, &along);
+        for (index, ((chain, &(_, _, edge)), &reversed)) in chains
+            .iter()
+            .zip(links.iter())
+            .zip(reversed.iter())
+            .enumerate()
+        {
+            let dir = dir_of(edge);
+            let (start_gap, end_gap) = if reversed {
+                (dir.has_head(), dir.has_tail())
+            } else {
+                (dir.has_tail(), dir.has_head())
+            };
+
+            let (tail, head) = (chain[0], chain[chain.len() - 1]);
+            let start = offsets[index].0;
+            let end = offsets[index].1;
+            let mut points = vec![(
+                x[tail] + start,
+                center(tail)
+                    + side_depth(&nodes[tail].shape, extents[tail], start)
+                    + gap(start_gap),
+            )];
+            for &v in chain[1..chain.len() - 1].iter() {
+                points.push((x[v], center(v)));
+            }
+            points.push((
+                x[head] + end,
+                center(head) - side_depth(&nodes[head].shape, extents[head], end) - gap(end_gap),
+            ));
+            // Each segment leaves and enters its nodes straight across the
+            // layers, like graphviz' splines do.
+            let mut cpts = vec![transform(points[0].0, points[0].1)];
+            for pair in points.windows(2) {
+                let ((a0, d0), (a1, d1)) = (pair[0], pair[1]);
+                let mid = (d0 + d1) / 2.0;
+                cpts.push(transform(a0, mid));
+                cpts.push(transform(a1, mid));
+                cpts.push(transform(a1, d1));
+            }
+            let mut points: Vec<dot::Coord> =
+                points.into_iter().map(|(a, d)| transform(a, d)).collect();
+            if reversed {
+                points.reverse();
+                cpts.reverse();
+            }
+
+            let label = edge_label(edge).map(|text| {
+                // Put the label next to the middle of the edge.
+                let mid = (points[(points.len() - 1) / 2] + points[points.len() / 2]) / 2.0;
+                let offset = if horizontal {
+                    dot::Coord::new(0.0, -1.0)
+                } else {
+                    dot::Coord::new(label_width(&text) / 2.0 + 1.0, 0.0)
+                };
+                dot::Label {
+                    text,
+                    coord: mid + offset,
+                }
+            });
+            graph.edges.push(laid_out(edge, dir, cpts, label));
+        }
+
+        // Self-loops leave and return to the side of their node as a single
+        // Bézier curve, ending before the node where there is an arrowhead.
+        // The curve reaches three quarters of the way to its middle control
+        // points.
+        for &(v, edge) in self_loops.iter() {
+            let dir = dir_of(edge);
+            let side = x[v] + along[v] / 2.0;
+            let offset = extents[v].1 / 3.0;
+            let bulge = side + (arrow_gap + loop_size) * 4.0 / 3.0;
+            let cpts = vec![
+                transform(side + gap(dir.has_tail()), center(v) - offset),
+                transform(bulge, center(v) - offset),
+                transform(bulge, center(v) + offset),
+                transform(side + gap(dir.has_head()), center(v) + offset),
+            ];
+            let far = side + arrow_gap + loop_size;
+            let label = edge_label(edge).map(|text| dot::Label {
+                coord: transform(far + label_extent(&text) / 2.0 + 1.0, center(v)),
+                text,
+            });
+            graph.edges.push(laid_out(edge, dir, cpts, label));
+        }
+
+        for node in nodes {
+            graph.nodes.insert(node.name.clone(), node);
+        }
+
+        // Move everything over where cluster boxes extend into the margin.
+        if let Some((a, b)) = layout_clusters(&self.subgraphs, &mut graph) {
+            let offset = dot::Coord::new((MARGIN - a.x).max(0.0), (MARGIN - a.y).max(0.0));
+            for node in graph.nodes.values_mut() {
+                node.translate(offset);
+            }
+            for edge in graph.edges.iter_mut() {
+                edge.translate(offset);
+            }
+            for cluster in graph.clusters.iter_mut() {
+                cluster.translate(offset);
+            }
+            graph.width = (graph.width + offset.x).max(b.x + offset.x + MARGIN);
+            graph.height = (graph.height + offset.y).max(b.y + offset.y + MARGIN);
+        }
+        cells_to_inches(&mut graph);
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Graph, String> {
+        Graph::from_dot(&mut text.as_bytes())
+    }
+
+    fn edges(graph: &Graph) -> Vec<(&str, &str)> {
+        graph
+            .edges
+            .iter()
+            .map(|e| (e.tail.as_str(), e.head.as_str()))
+            .collect()
+    }
+
+    fn node<'a>(graph: &'a Graph, id: &str) -> &'a Node {
+        graph.nodes.iter().find(|n| n.id == id).unwrap()
+    }
+
+    #[test]
+    fn parses_graph_header() {
+        let graph = parse("strict digraph G {}").unwrap();
+        assert!(graph.strict);
+        assert!(graph.directed);
+        assert_eq!(graph.id.as_deref(), Some("G"));
+
+        let graph = parse("GRAPH { }").unwrap();
+        assert!(!graph.strict);
+        assert!(!graph.directed);
+        assert_eq!(graph.id, None);
+    }
+
+    #[test]
+    fn parses_graph_attributes() {
+        let graph = parse("digraph { rankdir=LR; graph [label=x, fontsize=10] }").unwrap();
+        assert_eq!(graph.attributes["rankdir"], "LR");
+        assert_eq!(graph.attributes["label"], "x");
+        assert_eq!(graph.attributes["fontsize"], "10");
+    }
+
+    #[test]
+    fn applies_default_attributes_in_order() {
+        let graph = parse("digraph { a; node [shape=box]; b [label=B][color=red]; a }").unwrap();
+        let ids: Vec<&str> = graph.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(node(&graph, "a").attributes.is_empty());
+        let b = &node(&graph, "b").attributes;
+        assert_eq!(b["shape"], "box");
+        assert_eq!(b["label"], "B");
+        assert_eq!(b["color"], "red");
+    }
+
+    #[test]
+    fn parses_edge_chains() {
+        let graph = parse("digraph { edge [style=dashed]; a -> b -> c [color=red] }").unwrap();
+        assert_eq!(edges(&graph), [("a", "b"), ("b", "c")]);
+        for edge in graph.edges.iter() {
+            assert_eq!(edge.attributes["style"], "dashed");
+            assert_eq!(edge.attributes["color"], "red");
+        }
+    }
+
+    #[test]
+    fn parses_ports() {
+        let graph = parse("digraph { a:p1:n -> b:s }").unwrap();
+        assert_eq!(graph.edges[0].tail_port.as_deref(), Some("p1:n"));
+        assert_eq!(graph.edges[0].head_port.as_deref(), Some("s"));
+    }
+
+    #[test]
+    fn parses_subgraph_operands() {
+        let graph = parse("graph { a -- { b c } -- subgraph s { d } }").unwrap();
+        assert_eq!(
+            edges(&graph),
+            [("a", "b"), ("a", "c"), ("b", "d"), ("c", "d")]
+        );
+    }
+
+    #[test]
+    fn parses_nested_subgraphs() {
+        let graph = parse(
+            "digraph {
+                subgraph cluster_a { label=A; node [shape=box]; a; subgraph inner { b } }
+                subgraph { c }
+                subgraph cluster_a { d }
+                e
+            }",
+        )
+        .unwrap();
+        assert_eq!(graph.subgraphs.len(), 2);
+        let cluster = &graph.subgraphs[0];
+        assert!(cluster.is_cluster());
+        assert_eq!(cluster.attributes["label"], "A");
+        assert_eq!(cluster.nodes, ["a", "b", "d"]);
+        assert_eq!(cluster.subgraphs[0].id.as_deref(), Some("inner"));
+        assert_eq!(graph.subgraphs[1].id, None);
+        assert!(!graph.subgraphs[1].is_cluster());
+
+        // Defaults set within a subgraph do not leak out of it.
+        assert_eq!(node(&graph, "b").attributes["shape"], "box");
+        assert!(node(&graph, "e").attributes.is_empty());
+    }
+
+    #[test]
+    fn merges_edges_in_strict_graphs() {
+        let graph =
+            parse("strict graph { a -- b [color=red]; b -- a [style=bold]; a -- c }").unwrap();
+        assert_eq!(edges(&graph), [("a", "b"), ("a", "c")]);
+        assert_eq!(graph.edges[0].attributes["color"], "red");
+        assert_eq!(graph.edges[0].attributes["style"], "bold");
+
+        let graph = parse("strict digraph { a -> b; b -> a; a -> b }").unwrap();
+        assert_eq!(edges(&graph), [("a", "b"), ("b", "a")]);
+
+        let graph = parse("digraph { a -> b; a -> b }").unwrap();
+        assert_eq!(graph.edges.len(), 2);
+    }
+
+    #[test]
+    fn parses_quoted_ids() {
+        let graph = parse(
+            "digraph { \"a b\" [label=\"say \\\"hi\\\"\\n\"]; c [label=\"x\" + \"y\"]; d [label=\"e\\\nf\"] }",
+        )
+        .unwrap();
+        assert_eq!(graph.nodes[0].id, "a b");
+        assert_eq!(graph.nodes[0].attributes["label"], "say \"hi\"\\n");
+        assert_eq!(node(&graph, "c").attributes["label"], "xy");
+        assert_eq!(node(&graph, "d").attributes["label"], "ef");
+    }
+
+    #[test]
+    fn parses_html_ids() {
+        let graph = parse("digraph { a [label=<<b>bold</b> &lt;>] }").unwrap();
+        assert_eq!(graph.nodes[0].attributes["label"], "<<b>bold</b> &lt;>");
+    }
+
+    #[test]
+    fn parses_numerals_and_unicode_ids() {
+        let graph = parse("digraph { -.5 -> 1.25; café; a [width=.75] }").unwrap();
+        assert_eq!(edges(&graph), [("-.5", "1.25")]);
+        assert_eq!(graph.nodes[2].id, "café");
+        assert_eq!(node(&graph, "a").attributes["width"], ".75");
+    }
+
+    #[test]
+    fn skips_comments() {
+        let graph = parse(
+            "# preprocessor line
+            digraph { // line comment
+                a /* block
+                comment */ -> b
+              # another preprocessor line
+            }",
+        )
+        .unwrap();
+        assert_eq!(edges(&graph), [("a", "b")]);
+    }
+
+    #[test]
+    fn lays_out_empty_graphs() {
+        for source in [
+            "digraph {}",
+            "digraph { rankdir=LR }",
+            "graph { rankdir=BT }",
+        ] {
+            let graph = parse(source).unwrap().layout();
+            assert!(graph.nodes.is_empty());
+            assert!(graph.width > 0.0, "{source}: width {}", graph.width);
+            assert!(graph.height > 0.0, "{source}: height {}", graph.height);
+        }
+    }
+
+    #[test]
+    fn lays_out_edges_between_layers() {
+        let graph = parse("digraph { a -> b; a -> c }").unwrap().layout();
+        let (a, b, c) = (&graph.nodes["a"], &graph.nodes["b"], &graph.nodes["c"]);
+        assert!(a.coord.y < b.coord.y);
+        assert_eq!(b.coord.y, c.coord.y);
+        assert!(b.coord.x != c.coord.x);
+        assert_eq!(graph.edges.len(), 2);
+        for node in graph.nodes.values() {
+            assert!(node.coord.x - node.size.x / 2.0 >= 0.0);
+            assert!(node.coord.y - node.size.y / 2.0 >= 0.0);
+            assert!(node.coord.x + node.size.x / 2.0 <= graph.width);
+            assert!(node.coord.y + node.size.y / 2.0 <= graph.height);
+        }
+
+        let graph = parse("digraph { rankdir=LR; a -> b }").unwrap().layout();
+        assert!(graph.nodes["a"].coord.x < graph.nodes["b"].coord.x);
+    }
+
+    #[test]
+    fn lays_out_self_loops_beside_their_node() {
+        for source in [
+            "digraph { a -> a [label=loop]; a -> b }",
+            "digraph { rankdir=LR; a -> a [label=loop]; a -> b }",
+        ] {
+            let graph = parse(source).unwrap().layout();
+            let a = &graph.nodes["a"];
+            let edge = graph.edges.iter().find(|e| e.head == "a").unwrap();
+            assert_eq!(edge.tail, "a");
+            assert_eq!(edge.cpts.len(), 4, "{source}");
+            // The loop is right of the node, or below it for LR.
+            let beside = |c: dot::Coord| {
+                if source.contains("LR") {
+                    c.y >= a.coord.y + a.size.y / 2.0
+                } else {
+                    c.x >= a.coord.x + a.size.x / 2.0
+                }
+            };
+            assert!(edge.cpts.iter().all(|&c| beside(c)), "{source}");
+            let label = edge.label.as_ref().unwrap();
+            assert_eq!(label.text, "loop");
+            assert!(beside(label.coord), "{source}");
+            assert!(label.coord.x < graph.width && label.coord.y < graph.height);
+        }
+    }
+
+    #[test]
+    fn lays_out_clusters_around_their_nodes() {
+        let graph = parse(
+            "digraph {
+                subgraph cluster_outer { label=Outer; a; subgraph cluster_inner { b } }
+                subgraph cluster_empty { }
+                a -> b -> c
+            }",
+        )
+        .unwrap()
+        .layout();
+        let names: Vec<&str> = graph.clusters.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["cluster_outer", "cluster_inner"]);
+        let (outer, inner) = (&graph.clusters[0], &graph.clusters[1]);
+        let contains = |cluster: &dot::Cluster, a: dot::Coord, b: dot::Coord| {
+            cluster.a.x < a.x && cluster.a.y < a.y && b.x < cluster.b.x && b.y < cluster.b.y
+        };
+        let node_box = |name: &str| {
+            let node = &graph.nodes[name];
+            (node.coord - node.size / 2.0, node.coord + node.size / 2.0)
+        };
+        let (a, b, c) = (node_box("a"), node_box("b"), node_box("c"));
+        assert!(contains(outer, a.0, a.1));
+        assert!(contains(outer, inner.a, inner.b));
+        assert!(contains(inner, b.0, b.1));
+        assert!(!contains(inner, a.0, a.1));
+        assert!(!contains(outer, c.0, c.1));
+
+        // The label is at the top of the cluster, above its nodes.
+        let label = outer.label.as_ref().unwrap();
+        assert_eq!(label.text, "Outer");
+        assert!(outer.a.y < label.coord.y && label.coord.y < a.0.y);
+        assert!(inner.label.is_none());
+
+        // Everything still lies within the graph.
+        assert!(outer.a.x >= 0.0 && outer.a.y >= 0.0);
+        assert!(outer.b.x <= graph.width && outer.b.y <= graph.height);
+    }
+
+    #[test]
+    fn strips_html_labels() {
+        assert_eq!(strip_html("<<b>bold</b> text>"), "bold text");
+        assert_eq!(strip_html("<plain>"), "plain");
+        assert_eq!(strip_html("<a<br/>b>"), "a\nb");
+    }
+
+    #[test]
+    fn reports_lexical_errors() {
+        assert_eq!(
+            parse("digraph {\n a /* oops").unwrap_err(),
+            "unterminated comment starting on line 2"
+        );
+        assert_eq!(
+            parse("digraph {\n a [label=\"x]\n}").unwrap_err(),
+            "unterminated string starting on line 2"
+        );
+        assert_eq!(
+            parse("digraph { a [label=<x] }").unwrap_err(),
+            "unterminated HTML string starting on line 1"
+        );
+        assert_eq!(
+            parse("digraph { a [label=\"x\" + y] }").unwrap_err(),
+            "expected quoted string after '+' on line 1"
+        );
+        assert_eq!(
+            parse("digraph { - }").unwrap_err(),
+            "invalid numeral on line 1"
+        );
+        assert_eq!(
+            parse("digraph { a # b }").unwrap_err(),
+            "unexpected character '#' on line 1"
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        assert_eq!(
+            parse("network { }").unwrap_err(),
+            "expected graph or digraph but found ID network on line 1"
+        );
+        assert_eq!(
+            parse("digraph {\n a -- b\n}").unwrap_err(),
+            "edge operation -- used in digraph on line 2"
+        );
+        assert_eq!(
+            parse("graph { a -> b }").unwrap_err(),
+            "edge operation -> used in graph on line 1"
+        );
+        assert_eq!(
+            parse("digraph { a [label] }").unwrap_err(),
+            "expected '=' but found ']' on line 1"
+        );
+        assert_eq!(
+            parse("digraph { a -> }").unwrap_err(),
+            "expected '{' but found '}' on line 1"
+        );
+        assert_eq!(
+            parse("digraph { a").unwrap_err(),
+            "expected statement but found end of input"
+        );
+        assert_eq!(
+            parse("digraph { ; }").unwrap_err(),
+            "expected statement but found ';' on line 1"
+        );
+        assert_eq!(
+            parse("digraph { a }\ndigraph { b }").unwrap_err(),
+            "expected end of input after graph but found keyword digraph on line 2"
+        );
+        assert_eq!(
+            parse("graph { a } }").unwrap_err(),
+            "expected end of input after graph but found '}' on line 1"
+        );
+    }
+}
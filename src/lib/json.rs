@@ -0,0 +1,390 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A parsed JSON value. Objects retain the order of their members.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Parses a complete JSON document.
+    pub fn parse(text: &str) -> Result<Value, String> {
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+            line_no: 1,
+            depth: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.chars.peek() {
+            return Err(format!(
+                "unexpected character '{c}' after JSON value on line {}",
+                parser.line_no
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Returns the member with the given key if this is an object that has
+    /// it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        if let Value::Object(members) = self {
+            members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained string if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        if let Value::String(s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained number if this is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        if let Value::Number(x) = self {
+            Some(*x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained boolean if this is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Value::Bool(x) = self {
+            Some(*x)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained elements if this is an array.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        if let Value::Array(elements) = self {
+            Some(elements)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the contained members if this is an object.
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        if let Value::Object(members) = self {
+            Some(members)
+        } else {
+            None
+        }
+    }
+}
+
+/// Maximum nesting depth of arrays and objects, such that malicious input
+/// cannot overflow the stack. graphviz' output nests only a few levels deep.
+const MAX_DEPTH: usize = 128;
+
+/// Recursive-descent JSON parser state.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line_no: usize,
+
+    /// The number of arrays and objects the parser is currently within.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// Consumes the next character, keeping track of the line number.
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line_no += 1;
+        }
+        c
+    }
+
+    /// Skips over any whitespace.
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.next();
+        }
+    }
+
+    /// Consumes the given character after optional whitespace, throwing a
+    /// reasonable error message if something else is found.
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!(
+                "expected '{expected}' but found '{c}' on line {}",
+                self.line_no
+            )),
+            None => Err(format!("expected '{expected}' but found end of input")),
+        }
+    }
+
+    /// Consumes the given keyword, of which the first character has already
+    /// been peeked.
+    fn expect_keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        for expected in keyword.chars() {
+            if self.next() != Some(expected) {
+                return Err(format!("invalid keyword on line {}", self.line_no));
+            }
+        }
+        Ok(value)
+    }
+
+    /// Parses any value.
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{' | '[') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(format!(
+                        "nesting deeper than {MAX_DEPTH} levels on line {}",
+                        self.line_no
+                    ));
+                }
+                self.depth += 1;
+                let value = if self.chars.peek() == Some(&'{') {
+                    self.parse_object()
+                } else {
+                    self.parse_array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some('t') => self.expect_keyword("true", Value::Bool(true)),
+            Some('f') => self.expect_keyword("false", Value::Bool(false)),
+            Some('n') => self.expect_keyword("null", Value::Null),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(format!(
+                "unexpected character '{c}' on line {}",
+                self.line_no
+            )),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    /// Parses an object.
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.next();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.expect(':')?;
+            members.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => (),
+                Some('}') => return Ok(Value::Object(members)),
+                _ => {
+                    return Err(format!(
+                        "expected ',' or '}}' in object on line {}",
+                        self.line_no
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Parses an array.
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut elements = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.next();
+            return Ok(Value::Array(elements));
+        }
+        loop {
+            elements.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => (),
+                Some(']') => return Ok(Value::Array(elements)),
+                _ => {
+                    return Err(format!(
+                        "expected ',' or ']' in array on line {}",
+                        self.line_no
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Parses four hexadecimal digits of a \u escape sequence.
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| format!("invalid \\u escape sequence on line {}", self.line_no))?;
+            value = value * 16 + digit;
+        }
+        Ok(value)
+    }
+
+    /// Parses a string, including its quotes.
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(match self.next() {
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('u') => {
+                        let mut code = self.parse_hex4()?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            // High surrogate, must be followed by a low one.
+                            if self.next() != Some('\\') || self.next() != Some('u') {
+                                return Err(format!(
+                                    "unpaired surrogate in string on line {}",
+                                    self.line_no
+                                ));
+                            }
+                            let low = self.parse_hex4()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err(format!(
+                                    "unpaired surrogate in string on line {}",
+                                    self.line_no
+                                ));
+                            }
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        char::from_u32(code).ok_or_else(|| {
+                            format!("invalid code point in string on line {}", self.line_no)
+                        })?
+                    }
+                    Some(c) => c,
+                    None => return Err("unterminated string".to_string()),
+                }),
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    /// Parses a number.
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let mut number = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                number.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        number.parse().map(Value::Number).map_err(|e| {
+            format!(
+                "failed to parse number on line {} ('{number}'): {e}",
+                self.line_no
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_values() {
+        let value =
+            Value::parse(r#" {"a": [1, -2.5e1, true, false, null], "b": {}, "c": "x"} "#).unwrap();
+        assert_eq!(
+            value.get("a").and_then(Value::as_array),
+            Some(
+                &[
+                    Value::Number(1.0),
+                    Value::Number(-25.0),
+                    Value::Bool(true),
+                    Value::Bool(false),
+                    Value::Null,
+                ][..]
+            )
+        );
+        assert_eq!(value.get("b").and_then(Value::as_object), Some(&[][..]));
+        assert_eq!(value.get("c").and_then(Value::as_str), Some("x"));
+        assert_eq!(value.get("d"), None);
+        assert_eq!(value.get("c").and_then(Value::as_f64), None);
+    }
+
+    #[test]
+    fn keeps_member_order() {
+        let value = Value::parse(r#"{"z": 1, "a": 2, "m": 3}"#).unwrap();
+        let keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(keys, ["z", "a", "m"]);
+    }
+
+    #[test]
+    fn parses_escape_sequences() {
+        let value = Value::parse(r#""a\"\\\/\b\f\n\r\té😀""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"\\/\u{8}\u{c}\n\r\té😀"));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            Value::parse("{\"a\": 1}\n x").unwrap_err(),
+            "unexpected character 'x' after JSON value on line 2"
+        );
+        assert_eq!(Value::parse("").unwrap_err(), "unexpected end of input");
+        assert_eq!(Value::parse("\"abc").unwrap_err(), "unterminated string");
+        assert_eq!(
+            Value::parse("tru").unwrap_err(),
+            "invalid keyword on line 1"
+        );
+        assert_eq!(
+            Value::parse(r#""\ud83d""#).unwrap_err(),
+            "unpaired surrogate in string on line 1"
+        );
+        assert_eq!(
+            Value::parse(r#""\u12x4""#).unwrap_err(),
+            "invalid \\u escape sequence on line 1"
+        );
+        assert!(Value::parse("[1, 2").is_err());
+        assert!(Value::parse("[1 2]").is_err());
+        assert!(Value::parse("{\"a\" 1}").is_err());
+        assert!(Value::parse("{1: 2}").is_err());
+        assert!(Value::parse("1.2.3").is_err());
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Value::parse(&nested(MAX_DEPTH + 1)).unwrap_err(),
+            format!("nesting deeper than {MAX_DEPTH} levels on line 1")
+        );
+        assert!(Value::parse(&"{\"a\":[".repeat(100_000)).is_err());
+    }
+}
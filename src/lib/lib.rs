@@ -1,3 +1,9 @@
 pub mod canvas;
+pub mod color;
 pub mod dot;
-mod graph;
+pub mod graph;
+mod json;
+pub mod render;
+pub mod route;
+pub mod snap;
+pub mod xdot;
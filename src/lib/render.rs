@@ -0,0 +1,892 @@
+use crate::canvas::{Canvas, Shade, Stroke};
+use crate::color::{Color, Rgb};
+use crate::dot::{Cluster, Coord, Edge, Graph, Node};
+use crate::route;
+use crate::xdot::{Align, Op};
+
+/// How edges are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EdgeRouting {
+    /// Follow the splines of the layout, or the xdot drawing operations if
+    /// there are any.
+    #[default]
+    Splines,
+
+    /// Route edges on the character grid using only horizontal and vertical
+    /// lines, ignoring the splines of the layout. See
+    /// route::route_orthogonal().
+    Orthogonal,
+}
+
+/// How the interiors of filled shapes are shaded, see Canvas::fill().
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FillShade {
+    /// Pick a shade based on the fill color, where darker colors get denser
+    /// shades.
+    #[default]
+    Auto,
+
+    /// Always use the given shade.
+    Fixed(Shade),
+
+    /// Do not shade filled shapes, such that they are only distinguishable
+    /// by their background color if colors are enabled.
+    None,
+}
+
+impl FillShade {
+    /// Returns the shade for a shape filled with the given color, which is
+    /// None if it is not known.
+    fn for_color(self, color: Option<Rgb>) -> Option<Shade> {
+        match self {
+            FillShade::Auto => Some(color.map_or(Shade::Medium, Shade::for_color)),
+            FillShade::Fixed(shade) => Some(shade),
+            FillShade::None => None,
+        }
+    }
+}
+
+/// Options for draw_graph().
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub edge_routing: EdgeRouting,
+    pub fill_shade: FillShade,
+}
+
+/// Draws a laid-out graph onto a canvas. Graphs, clusters, nodes and edges
+/// that come with xdot drawing operations are drawn by replaying those, such
+/// that any shape graphviz knows about is drawn the way graphviz draws it.
+/// Everything else is drawn based on its attributes.
+pub fn draw_graph(canvas: &mut Canvas, graph: &Graph, options: &Options) {
+    // graphviz fills the background of every graph with white unless told
+    // otherwise, which would cover the whole terminal.
+    let has_background = graph.attributes.contains_key("bgcolor");
+    draw_ops(
+        canvas,
+        graph
+            .draw
+            .iter()
+            .filter(|op| has_background || !matches!(op, Op::FillColor(_))),
+        false,
+        FillShade::None,
+    );
+    for cluster in graph.clusters.iter() {
+        draw_cluster(canvas, cluster, options.fill_shade);
+    }
+
+    // Draw the nodes in reading order, such that any footnotes are numbered
+    // in that order as well.
+    let mut nodes: Vec<&Node> = graph.nodes.values().collect();
+    nodes.sort_by(|a, b| {
+        (a.coord.y, a.coord.x)
+            .partial_cmp(&(b.coord.y, b.coord.x))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let nodes: Vec<&Node> = nodes
+        .into_iter()
+        .filter(|node| !has_style(&node.style, "invis"))
+        .collect();
+    for node in nodes.iter() {
+        if node.draw.is_empty() {
+            draw_node(canvas, node, options.fill_shade);
+        } else {
+            draw_ops(canvas, &node.draw, false, options.fill_shade);
+        }
+    }
+
+    // Keep edges out of nodes, such that lines that end up in the wrong
+    // character after rounding neither overdraw the labels nor leave a gap
+    // to the outline.
+    for node in nodes.iter() {
+        let outline = Outline::of(node);
+        canvas.clip(
+            node.coord - node.size / 2.0,
+            node.coord + node.size / 2.0,
+            |c| outline.contains(node, c),
+        );
+    }
+    let routes = match options.edge_routing {
+        EdgeRouting::Splines => vec![None; graph.edges.len()],
+        EdgeRouting::Orthogonal => route::route_orthogonal(graph, canvas.char_size()),
+    };
+    for (edge, route) in graph.edges.iter().zip(routes) {
+        if has_style(&edge.style, "invis") {
+            continue;
+        }
+        match route {
+            Some(points) => draw_routed_edge(canvas, edge, &points),
+            None => draw_edge(canvas, graph, edge),
+        }
+    }
+    canvas.clear_clip();
+
+    // Edge labels go last, as they are placed around whatever is already on
+    // the canvas.
+    for edge in graph.edges.iter() {
+        if has_style(&edge.style, "invis") {
+            continue;
+        }
+        let label_ops = || {
+            edge.draw
+                .iter()
+                .chain(&edge.head_label_draw)
+                .chain(&edge.tail_label_draw)
+        };
+        if label_ops().any(|op| matches!(op, Op::Text { .. })) {
+            draw_ops(
+                canvas,
+                label_ops()
+                    .filter(|op| matches!(op, Op::Text { .. } | Op::Font { .. } | Op::PenColor(_))),
+                true,
+                FillShade::None,
+            );
+        } else if let Some(label) = &edge.label {
+            canvas.set_pen_color(font_color(&edge.attributes));
+            canvas.draw_centered_label(label.coord, &label.text);
+            canvas.set_pen_color(None);
+        }
+    }
+}
+
+/// Pen state while replaying xdot drawing operations.
+struct Pen {
+    /// Whether lines are visible based on the pen color, which is false for
+    /// fully transparent colors.
+    color_visible: bool,
+
+    /// Whether an invis style was set, which hides lines regardless of the
+    /// pen color.
+    invisible: bool,
+
+    /// The stroke attributes set by style operations so far. graphviz emits
+    /// one style operation per item of the style attribute, so they add up.
+    stroke: StrokeStyle,
+
+    /// The current font size, used to find the vertical center of a line of
+    /// text from its baseline.
+    font_size: f64,
+
+    /// The current fill color, used for filled shapes, or None if they are
+    /// not filled, which includes transparent fill colors.
+    fill: Option<Color>,
+}
+
+impl Pen {
+    /// Returns whether lines are currently visible.
+    fn visible(&self) -> bool {
+        self.color_visible && !self.invisible
+    }
+}
+
+impl Default for Pen {
+    fn default() -> Self {
+        Pen {
+            color_visible: true,
+            invisible: false,
+            stroke: StrokeStyle::default(),
+            fill: None,
+            // graphviz' default font size of 14 points, in inches.
+            font_size: 14.0 / 72.0,
+        }
+    }
+}
+
+/// Returns whether a graphviz style attribute, which is a comma-separated
+/// list of styles, contains the given style.
+fn has_style(style: &str, name: &str) -> bool {
+    style.split(',').any(|s| s.trim() == name)
+}
+
+/// The stroke attributes of a graphviz style, which are independent of each
+/// other: a line can be both dashed and bold.
+#[derive(Default)]
+struct StrokeStyle {
+    /// Stroke::Dashed, Stroke::Dotted or Stroke::Solid.
+    pattern: Stroke,
+    bold: bool,
+}
+
+impl StrokeStyle {
+    /// Updates the attributes from a graphviz style attribute or xdot style
+    /// operation. graphviz uses setlinewidth for bold lines in xdot.
+    fn apply(&mut self, style: &str) {
+        for s in style.split(',').map(str::trim) {
+            let width = s
+                .strip_prefix("setlinewidth(")
+                .and_then(|s| s.strip_suffix(')'))
+                .and_then(|width| width.parse::<f64>().ok());
+            match s {
+                "solid" => self.pattern = Stroke::Solid,
+                "dashed" => self.pattern = Stroke::Dashed,
+                "dotted" => self.pattern = Stroke::Dotted,
+                "bold" => self.bold = true,
+                _ => {
+                    if let Some(width) = width {
+                        self.bold = width >= 2.0;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the stroke to draw lines with. The canvas has no bold dashed
+    /// lines, so the dash pattern takes precedence over boldness.
+    fn stroke(&self) -> Stroke {
+        match self.pattern {
+            Stroke::Solid if self.bold => Stroke::Bold,
+            pattern => pattern,
+        }
+    }
+}
+
+/// Returns the stroke style for lines, based on a graphviz style attribute.
+fn stroke_of(style: &str) -> Stroke {
+    let mut stroke = StrokeStyle::default();
+    stroke.apply(style);
+    stroke.stroke()
+}
+
+/// Returns whether the given xdot color is visible at all. graphviz uses
+/// fully transparent colors for example for the background of the graph.
+fn is_visible(color: &str) -> bool {
+    !Color::parse(color).is_ok_and(|color| color.is_transparent())
+}
+
+/// Returns the color to draw lines and text with for the given graphviz
+/// color. Black is graphviz' default color, so it maps to the default color
+/// of the terminal instead, which keeps it visible on dark backgrounds.
+fn pen_color(color: &Color) -> Option<Rgb> {
+    color.to_rgb().filter(|&rgb| rgb != Rgb::new(0, 0, 0))
+}
+
+/// Parses a color given as a string, such as an attribute value or an xdot
+/// color, ignoring colors that cannot be parsed.
+fn parse_color(color: &str) -> Option<Color> {
+    Color::parse(color).ok()
+}
+
+/// Returns the color to draw labels with, based on the fontcolor attribute.
+fn font_color(attributes: &std::collections::HashMap<String, String>) -> Option<Rgb> {
+    attributes
+        .get("fontcolor")
+        .and_then(|color| parse_color(color))
+        .and_then(|color| pen_color(&color))
+}
+
+/// Returns whether the given coordinate lies within the given closed
+/// polygon, using the even-odd rule.
+fn polygon_contains(points: &[Coord], coord: Coord) -> bool {
+    // Ray cast in the positive X direction.
+    let mut inside = false;
+    let Some(mut prev) = points.last() else {
+        return false;
+    };
+    for point in points.iter() {
+        if (point.y > coord.y) != (prev.y > coord.y)
+            && coord.x < prev.x + (point.x - prev.x) * (coord.y - prev.y) / (point.y - prev.y)
+        {
+            inside = !inside;
+        }
+        prev = point;
+    }
+    inside
+}
+
+/// Returns the bounding box of the given points.
+fn bounds(points: &[Coord]) -> (Coord, Coord) {
+    points.iter().fold(
+        (
+            Coord::new(f64::INFINITY, f64::INFINITY),
+            Coord::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        ),
+        |(a, b), p| {
+            (
+                Coord::new(a.x.min(p.x), a.y.min(p.y)),
+                Coord::new(b.x.max(p.x), b.y.max(p.y)),
+            )
+        },
+    )
+}
+
+/// Replays xdot drawing operations onto a canvas. Text is written at exactly
+/// the position graphviz put it, unless floating_text is set, in which case
+/// it is placed around existing text like edge labels are. Filled shapes are
+/// shaded according to fill_shade.
+fn draw_ops<'a, I: IntoIterator<Item = &'a Op>>(
+    canvas: &mut Canvas,
+    ops: I,
+    floating_text: bool,
+    fill_shade: FillShade,
+) {
+    let mut pen = Pen::default();
+    for op in ops {
+        let fill = pen.fill.as_ref().map(Color::to_rgb);
+        match (op, fill) {
+            (
+                Op::Ellipse {
+                    filled: true,
+                    center,
+                    radii,
+                },
+                Some(fill),
+            ) => {
+                let shade = fill_shade.for_color(fill);
+                canvas.fill(*center - *radii, *center + *radii, fill, shade, |c| {
+                    let d = c - *center;
+                    (d.x / radii.x).powi(2) + (d.y / radii.y).powi(2) <= 1.0
+                });
+            }
+            (
+                Op::Polygon {
+                    filled: true,
+                    points,
+                }
+                | Op::Bezier {
+                    filled: true,
+                    points,
+                },
+                Some(fill),
+            ) => {
+                let (a, b) = bounds(points);
+                let shade = fill_shade.for_color(fill);
+                canvas.fill(a, b, fill, shade, |c| polygon_contains(points, c));
+            }
+            _ => (),
+        }
+        match op {
+            Op::Ellipse { center, radii, .. } if pen.visible() => {
+                canvas.draw_ellipse(*center, *radii)
+            }
+            Op::Polygon { points, .. } if pen.visible() => canvas.draw_polygon(points),
+            Op::Polyline { points } if pen.visible() => canvas.draw_polyline(points),
+            Op::Bezier { points, .. } if pen.visible() => canvas.draw_bezier(points),
+            Op::Image { coord, size, .. } if pen.visible() => {
+                canvas.draw_rect(*coord, *coord + *size)
+            }
+            Op::Text {
+                coord,
+                align,
+                width,
+                text,
+            } => {
+                // graphviz positions text by its baseline.
+                let mut center = *coord - Coord::new(0.0, pen.font_size * 0.3);
+                if floating_text {
+                    center.x += match align {
+                        Align::Left => width / 2.0,
+                        Align::Center => 0.0,
+                        Align::Right => -width / 2.0,
+                    };
+                    canvas.draw_centered_label(center, text);
+                } else {
+                    canvas.draw_aligned_text(center, *align, text);
+                }
+            }
+            Op::PenColor(color) => {
+                pen.color_visible = is_visible(color);
+                canvas.set_pen_color(parse_color(color).and_then(|color| pen_color(&color)));
+            }
+            Op::FillColor(color) => {
+                pen.fill = parse_color(color).filter(|color| !color.is_transparent())
+            }
+            Op::Font { size, .. } => pen.font_size = *size,
+            Op::Style(style) if style == "invis" => pen.invisible = true,
+            Op::Style(style) => {
+                pen.stroke.apply(style);
+                canvas.set_stroke(pen.stroke.stroke());
+            }
+            _ => (),
+        }
+    }
+    canvas.set_pen_color(None);
+    canvas.set_stroke(Stroke::Solid);
+}
+
+/// Draws the outline and label of a cluster.
+fn draw_cluster(canvas: &mut Canvas, cluster: &Cluster, fill_shade: FillShade) {
+    if !cluster.draw.is_empty() {
+        draw_ops(canvas, &cluster.draw, false, fill_shade);
+        return;
+    }
+    let attribute = |name: &str| cluster.attributes.get(name).map(String::as_str);
+    let style = attribute("style").unwrap_or("");
+    if has_style(style, "invis") {
+        return;
+    }
+    let a = Coord::new(cluster.a.x.min(cluster.b.x), cluster.a.y.min(cluster.b.y));
+    let b = Coord::new(cluster.a.x.max(cluster.b.x), cluster.a.y.max(cluster.b.y));
+    // Only clusters that are explicitly filled are shaded, as shading every
+    // cluster with a background color would bury the nodes within.
+    let filled = has_style(style, "filled");
+    let fill = if filled {
+        attribute("fillcolor").or(attribute("color"))
+    } else {
+        attribute("bgcolor")
+    };
+    if let Some(fill) = fill
+        .and_then(parse_color)
+        .filter(|color| !color.is_transparent())
+    {
+        let fill = fill.to_rgb();
+        let shade = if filled {
+            fill_shade.for_color(fill)
+        } else {
+            None
+        };
+        canvas.fill(a, b, fill, shade, |_| true);
+    }
+    canvas.set_pen_color(
+        attribute("pencolor")
+            .or(attribute("color"))
+            .and_then(parse_color)
+            .and_then(|color| pen_color(&color)),
+    );
+    canvas.set_stroke(stroke_of(style));
+    if has_style(style, "rounded") {
+        canvas.draw_rounded_rect(a, b);
+    } else {
+        canvas.draw_rect(a, b);
+    }
+    canvas.set_stroke(Stroke::Solid);
+    if let Some(label) = &cluster.label {
+        canvas.set_pen_color(font_color(&cluster.attributes));
+        canvas.draw_centered_label(label.coord, &label.text);
+    }
+    canvas.set_pen_color(None);
+}
+
+/// The outline of a node, used both for drawing it and for finding where
+/// edges meet it.
+enum Outline {
+    /// The node has no visible outline. Its bounding box is used for finding
+    /// where edges meet it.
+    Invisible,
+
+    /// An ellipse that fills the bounding box of the node.
+    Ellipse,
+
+    /// A closed polygon.
+    Polygon(Vec<Coord>),
+}
+
+impl Outline {
+    /// Returns the outline for a node based on its shape. Shapes that
+    /// graphviz knows about but that are not explicitly supported here are
+    /// drawn as boxes, which is also what graphviz does for unknown shapes.
+    fn of(node: &Node) -> Outline {
+        let points: &[(f64, f64)] = match &node.shape[..] {
+            "plaintext" | "plain" | "none" => return Outline::Invisible,
+            "ellipse" | "oval" | "circle" | "doublecircle" | "point" | "egg" => {
+                return Outline::Ellipse
+            }
+            "diamond" => &[(0.5, 0.0), (1.0, 0.5), (0.5, 1.0), (0.0, 0.5)],
+            "triangle" => &[(0.5, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            "invtriangle" => &[(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)],
+            "pentagon" => &[
+                (0.5, 0.0),
+                (1.0, 0.38),
+                (0.81, 1.0),
+                (0.19, 1.0),
+                (0.0, 0.38),
+            ],
+            "hexagon" => &[
+                (0.0, 0.5),
+                (0.25, 0.0),
+                (0.75, 0.0),
+                (1.0, 0.5),
+                (0.75, 1.0),
+                (0.25, 1.0),
+            ],
+            "octagon" => &[
+                (0.3, 0.0),
+                (0.7, 0.0),
+                (1.0, 0.3),
+                (1.0, 0.7),
+                (0.7, 1.0),
+                (0.3, 1.0),
+                (0.0, 0.7),
+                (0.0, 0.3),
+            ],
+            "parallelogram" => &[(0.25, 0.0), (1.0, 0.0), (0.75, 1.0), (0.0, 1.0)],
+            "trapezium" => &[(0.25, 0.0), (0.75, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            "invtrapezium" => &[(0.0, 0.0), (1.0, 0.0), (0.75, 1.0), (0.25, 1.0)],
+            "house" => &[(0.5, 0.0), (1.0, 0.35), (1.0, 1.0), (0.0, 1.0), (0.0, 0.35)],
+            "invhouse" => &[(0.0, 0.0), (1.0, 0.0), (1.0, 0.65), (0.5, 1.0), (0.0, 0.65)],
+            _ => &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+        };
+        let a = node.coord - node.size / 2.0;
+        Outline::Polygon(
+            points
+                .iter()
+                .map(|&(x, y)| Coord::new(a.x + x * node.size.x, a.y + y * node.size.y))
+                .collect(),
+        )
+    }
+
+    /// Returns whether the given coordinate lies within this outline for the
+    /// given node.
+    fn contains(&self, node: &Node, coord: Coord) -> bool {
+        let d = coord - node.coord;
+        match self {
+            Outline::Invisible => d.x.abs() <= node.size.x / 2.0 && d.y.abs() <= node.size.y / 2.0,
+            Outline::Ellipse => {
+                let x = d.x / (node.size.x / 2.0);
+                let y = d.y / (node.size.y / 2.0);
+                x * x + y * y <= 1.0
+            }
+            Outline::Polygon(points) => polygon_contains(points, coord),
+        }
+    }
+}
+
+/// Returns the fraction of the bounding box of a node with the given shape
+/// that is available for its label, as the largest centered rectangle that
+/// fits within its outline. The layout engine sizes nodes to match.
+pub(crate) fn label_fraction(shape: &str) -> f64 {
+    match shape {
+        "ellipse" | "oval" | "circle" | "doublecircle" | "point" | "egg" => {
+            std::f64::consts::FRAC_1_SQRT_2
+        }
+        "triangle" | "invtriangle" | "diamond" => 0.5,
+        "pentagon" | "hexagon" | "octagon" | "house" | "invhouse" => 0.8,
+        "parallelogram" | "trapezium" | "invtrapezium" => 0.8,
+        _ => 1.0,
+    }
+}
+
+/// Draws the outline and label of a node. Filled nodes are shaded according
+/// to fill_shade.
+fn draw_node(canvas: &mut Canvas, node: &Node, fill_shade: FillShade) {
+    let outline = Outline::of(node);
+    let a = node.coord - node.size / 2.0;
+    let b = node.coord + node.size / 2.0;
+    let radii = node.size / 2.0;
+    if has_style(&node.style, "filled") && !node.fillcolor.is_transparent() {
+        let fill = node.fillcolor.to_rgb();
+        let shade = fill_shade.for_color(fill);
+        canvas.fill(a, b, fill, shade, |c| outline.contains(node, c));
+    }
+    canvas.set_pen_color(pen_color(&node.color));
+    canvas.set_stroke(stroke_of(&node.style));
+    let rounded = has_style(&node.style, "rounded");
+    match (&node.shape[..], &outline) {
+        ("box" | "rect" | "rectangle" | "square", _) if rounded => canvas.draw_rounded_rect(a, b),
+        ("cylinder", _) => {
+            let cap = Coord::new(radii.x, node.size.y * 0.15);
+            let top = Coord::new(node.coord.x, a.y + cap.y);
+            let bottom = Coord::new(node.coord.x, b.y - cap.y);
+            canvas.draw_ellipse(top, cap);
+            canvas.draw_line(Coord::new(a.x, top.y), Coord::new(a.x, bottom.y));
+            canvas.draw_line(Coord::new(b.x, top.y), Coord::new(b.x, bottom.y));
+            canvas.draw_arc(bottom, cap, 0.0, std::f64::consts::PI);
+        }
+        ("note", _) => {
+            let fold = node.size.x.min(node.size.y) * 0.25;
+            canvas.draw_polygon(&[
+                a,
+                Coord::new(b.x - fold, a.y),
+                Coord::new(b.x, a.y + fold),
+                b,
+                Coord::new(a.x, b.y),
+            ]);
+            canvas.draw_polyline(&[
+                Coord::new(b.x - fold, a.y),
+                Coord::new(b.x - fold, a.y + fold),
+                Coord::new(b.x, a.y + fold),
+            ]);
+        }
+        ("doublecircle", _) => {
+            let inner = radii - canvas.char_size();
+            canvas.draw_ellipse(node.coord, radii);
+            if inner.x > 0.0 && inner.y > 0.0 {
+                canvas.draw_ellipse(node.coord, inner);
+            }
+        }
+        (_, Outline::Invisible) => (),
+        (_, Outline::Ellipse) => canvas.draw_ellipse(node.coord, radii),
+        (_, Outline::Polygon(points)) => canvas.draw_polygon(points),
+    }
+
+    canvas.set_stroke(Stroke::Solid);
+
+    let label_radii = radii * label_fraction(&node.shape);
+    canvas.set_pen_color(font_color(&node.attributes));
+    canvas.draw_label(
+        node.coord - label_radii,
+        node.coord + label_radii,
+        &node.label,
+    );
+    canvas.set_pen_color(None);
+}
+
+/// Draws an edge, including its arrowheads.
+fn draw_edge(canvas: &mut Canvas, graph: &Graph, edge: &Edge) {
+    if edge.draw.is_empty() {
+        canvas.set_pen_color(pen_color(&edge.color));
+        canvas.set_stroke(stroke_of(&edge.style));
+        canvas.draw_bezier(&edge.cpts);
+    } else {
+        draw_ops(
+            canvas,
+            edge.draw.iter().filter(|op| !matches!(op, Op::Text { .. })),
+            false,
+            FillShade::None,
+        );
+        canvas.set_pen_color(pen_color(&edge.color));
+    }
+    if let Some(head) = graph.nodes.get(&edge.head) {
+        draw_edge_end(canvas, head, edge.cpts.iter().rev(), edge.dir.has_head());
+    }
+    if let Some(tail) = graph.nodes.get(&edge.tail) {
+        draw_edge_end(canvas, tail, edge.cpts.iter(), edge.dir.has_tail());
+    }
+    canvas.set_pen_color(None);
+    canvas.set_stroke(Stroke::Solid);
+}
+
+/// Draws an edge along a route from route::route_orthogonal(), including its
+/// arrowheads.
+fn draw_routed_edge(canvas: &mut Canvas, edge: &Edge, points: &[Coord]) {
+    let n = points.len();
+    canvas.set_pen_color(pen_color(&edge.color));
+    canvas.set_stroke(stroke_of(&edge.style));
+    canvas.draw_polyline(&points[1..n - 1]);
+    if edge.dir.has_tail() {
+        canvas.draw_arrow(points[1], points[0]);
+    } else {
+        canvas.draw_line_to_outline(points[1], points[0]);
+    }
+    if edge.dir.has_head() {
+        canvas.draw_arrow(points[n - 2], points[n - 1]);
+    } else {
+        canvas.draw_line_to_outline(points[n - 2], points[n - 1]);
+    }
+    canvas.set_pen_color(None);
+    canvas.set_stroke(Stroke::Solid);
+}
+
+/// Connects the end of an edge to the outline of the given node, optionally
+/// with an arrowhead pointing into the node. The control points must be
+/// given starting from the end of the edge that is connected.
+///
+/// graphviz ends the edge spline at the base of the arrowhead, so the gap
+/// between the end of the spline and the node outline is bridged with a
+/// straight line along the direction of the last spline segment. This line
+/// starts a tiny bit before the end of the spline, such that the arrow still
+/// has a direction if the spline does end on the outline. Ends without an
+/// arrowhead are bridged as well, as a spline that ends on the outline may
+/// still stop a character short of it after rounding.
+fn draw_edge_end<'a, I: Iterator<Item = &'a Coord>>(
+    canvas: &mut Canvas,
+    node: &Node,
+    mut cpts: I,
+    arrow: bool,
+) {
+    let Some(&end) = cpts.next() else {
+        return;
+    };
+    let Some(direction) = cpts
+        .map(|&cpt| end - cpt)
+        .find(|d| d.x != 0.0 || d.y != 0.0)
+    else {
+        return;
+    };
+    let nudge = direction * (1.0e-6 / direction.x.hypot(direction.y));
+    if Outline::of(node).contains(node, end) {
+        // The spline itself already crosses the outline, for example with
+        // clip=false, so only the arrowhead is left to draw, where it does.
+        let outside = end - nudge * ((node.size.x + node.size.y) / 1.0e-6);
+        if let (true, Some(tip)) = (arrow, find_outline(node, outside, direction)) {
+            canvas.draw_arrow(tip - nudge, tip);
+        }
+        return;
+    }
+    let tip = find_outline(node, end, direction).unwrap_or(end);
+    if arrow {
+        canvas.draw_arrow(end - nudge, tip);
+    } else {
+        // Without an arrowhead, there is only something to bridge if the
+        // spline ends in a different character than the outline.
+        let size = canvas.char_size();
+        let cell = |c: Coord| ((c.x / size.x).floor(), (c.y / size.y).floor());
+        if cell(end) != cell(tip) {
+            canvas.draw_line_to_outline(end - nudge, tip);
+        }
+    }
+}
+
+/// Maximum number of steps find_outline() takes along a ray.
+const MAX_OUTLINE_STEPS: usize = 10_000;
+
+/// Finds the point where a ray starting at the given coordinate and
+/// extending in the given direction first enters the given node, if it does
+/// so within a reasonable distance. If the start of the ray already lies
+/// within the node, the start is returned.
+fn find_outline(node: &Node, start: Coord, direction: Coord) -> Option<Coord> {
+    let outline = Outline::of(node);
+    if outline.contains(node, start) {
+        return Some(start);
+    }
+
+    // March along the ray in small steps until we end up inside the node.
+    // Nodes without an area cannot be entered, and the number of steps is
+    // capped such that far-away or huge nodes cannot stall drawing.
+    let length = direction.x.hypot(direction.y);
+    let size = node.size.x.min(node.size.y);
+    if !(size > 0.0 && size.is_finite() && length > 0.0 && length.is_finite()) {
+        return None;
+    }
+    let reach = (start - node.coord).x.hypot((start - node.coord).y) + node.size.x + node.size.y;
+    let step = direction / length * (size / 50.0);
+    let steps = (reach / (size / 50.0)).min(MAX_OUTLINE_STEPS as f64) as usize;
+    let mut outside = start;
+    let mut inside = None;
+    for _ in 0..steps {
+        let next = outside + step;
+        if outline.contains(node, next) {
+            inside = Some(next);
+            break;
+        }
+        outside = next;
+    }
+
+    // Refine the outline crossing using bisection.
+    let mut inside = inside?;
+    for _ in 0..16 {
+        let mid = (outside + inside) / 2.0;
+        if outline.contains(node, mid) {
+            inside = mid;
+        } else {
+            outside = mid;
+        }
+    }
+    Some(inside)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::{BitmapFont, RenderOptions, Sizing};
+    use crate::dot::EdgeDir;
+
+    #[test]
+    fn draws_edges_between_zero_size_nodes() {
+        let plain = "graph 1 3 3\n\
+            node a 1 2.5 0 0 a solid ellipse black lightgrey\n\
+            node b 1 0.5 0.75 0.5 b solid box black lightgrey\n\
+            node c 2 0.5 0 0.5 c solid box black lightgrey\n\
+            edge a b 4 1 2.4 1 1.8 1 1.2 1 0.75 solid black\n\
+            edge b a 4 1 0.75 1 1.2 1 1.8 1 2.4 solid black\n\
+            edge a c 4 1 2.5 1.5 2 2 1 2 0.5 solid black\n\
+            stop\n";
+        let graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+        for edge_routing in [EdgeRouting::Splines, EdgeRouting::Orthogonal] {
+            let mut canvas = Canvas::for_graph(&graph, Sizing::default());
+            let options = Options {
+                edge_routing,
+                ..Options::default()
+            };
+            draw_graph(&mut canvas, &graph, &options);
+            let text = canvas.render_to_string(&BitmapFont::default(), &RenderOptions::default());
+            assert!(text.contains('b'), "{text}");
+        }
+    }
+
+    #[test]
+    fn combines_stroke_attributes() {
+        assert_eq!(stroke_of(""), Stroke::Solid);
+        assert_eq!(stroke_of("bold"), Stroke::Bold);
+        assert_eq!(stroke_of("dashed, bold"), Stroke::Dashed);
+        assert_eq!(stroke_of("bold,dotted"), Stroke::Dotted);
+        assert_eq!(stroke_of("dashed,solid,setlinewidth(3)"), Stroke::Bold);
+    }
+
+    /// Replays the given ops followed by a horizontal line and renders the
+    /// result.
+    fn draw_line_with(ops: Vec<Op>) -> String {
+        let mut canvas = Canvas::new(20.0, Coord::new(1.0, 1.0));
+        let line = Op::Polyline {
+            points: vec![Coord::new(1.0, 2.0), Coord::new(18.0, 2.0)],
+        };
+        draw_ops(
+            &mut canvas,
+            ops.iter().chain([&line]),
+            false,
+            FillShade::None,
+        );
+        canvas.render_to_string(&BitmapFont::default(), &RenderOptions::default())
+    }
+
+    #[test]
+    fn keeps_pen_state_across_style_ops() {
+        let style = |s: &str| Op::Style(s.to_string());
+        let dashed = draw_line_with(vec![style("dashed")]);
+        assert_ne!(dashed, draw_line_with(vec![]));
+        assert_ne!(dashed, draw_line_with(vec![style("bold")]));
+        assert_eq!(draw_line_with(vec![style("dashed"), style("bold")]), dashed);
+
+        let invisible = draw_line_with(vec![style("invis"), Op::PenColor("red".to_string())]);
+        assert!(invisible.trim().is_empty(), "{invisible}");
+    }
+
+    #[test]
+    fn skips_invisible_nodes_and_edges() {
+        let render = |plain: &str| {
+            let graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+            let mut canvas = Canvas::for_graph(&graph, Sizing::default());
+            draw_graph(&mut canvas, &graph, &Options::default());
+            canvas.render_to_string(&BitmapFont::default(), &RenderOptions::default())
+        };
+        let hidden = render(
+            "graph 1 2 3\n\
+            node a 1 2.5 0.75 0.5 a solid box black lightgrey\n\
+            node b 1 0.5 0.75 0.5 b invis box black lightgrey\n\
+            node c 1 1.5 0.75 0.5 c solid box black lightgrey\n\
+            edge a c 4 1 2.25 1 2 1 2 1 1.75 invis black\n\
+            stop\n",
+        );
+        let without = render(
+            "graph 1 2 3\n\
+            node a 1 2.5 0.75 0.5 a solid box black lightgrey\n\
+            node c 1 1.5 0.75 0.5 c solid box black lightgrey\n\
+            stop\n",
+        );
+        assert_eq!(hidden, without);
+        assert!(hidden.contains('a') && !hidden.contains('b'), "{hidden}");
+    }
+
+    #[test]
+    fn connects_splines_that_end_inside_a_node() {
+        let plain = "graph 1 2 3\n\
+            node a 1 2.5 0.75 0.5 a solid box black lightgrey\n\
+            node b 1 0.75 1.5 0.5 label solid box black lightgrey\n\
+            edge a b 4 1 2.25 1 1.8 1 1.2 1 0.75 solid black\n\
+            stop\n";
+        let mut graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+        let render = |graph: &Graph| -> Vec<String> {
+            let mut canvas = Canvas::for_graph(graph, Sizing::default());
+            draw_graph(&mut canvas, graph, &Options::default());
+            let text = canvas.render_to_string(&BitmapFont::default(), &RenderOptions::default());
+            text.lines().map(str::to_string).collect()
+        };
+        let mut nodes_only = graph.clone();
+        nodes_only.edges.clear();
+        let nodes_only = render(&nodes_only);
+        let label = nodes_only
+            .iter()
+            .position(|row| row.contains("label"))
+            .unwrap();
+        let border = label - 2;
+
+        for (dir, end) in [(EdgeDir::Forward, 'v'), (EdgeDir::None, '|')] {
+            graph.edges[0].dir = dir;
+            let rows = render(&graph);
+            assert_eq!(rows[label], nodes_only[label], "{rows:#?}");
+            let column = rows[border - 2].find('|').unwrap();
+            let at = |row: usize| rows[row].chars().nth(column).unwrap();
+            assert_eq!(at(border - 1), end, "{rows:#?}");
+            assert_ne!(at(border), ' ', "{rows:#?}");
+            assert_eq!(at(border + 1), ' ', "{rows:#?}");
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use crate::dot::{Coord, Graph, Node};
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Cost of moving to an adjacent cell.
+const STEP_COST: u32 = 2;
+
+/// Additional cost of changing direction.
+const BEND_COST: u32 = 6;
+
+/// Additional cost of crossing a previously routed edge.
+const CROSSING_COST: u32 = 8;
+
+/// Additional cost of running along a previously routed edge, which makes
+/// the edges indistinguishable.
+const OVERLAP_COST: u32 = 40;
+
+/// Fraction of each side of a node that edges may attach to, centered on
+/// the middle of the side. Attaching near corners would miss the outline of
+/// rounded shapes.
+const PORT_FRACTION: f64 = 0.6;
+
+/// Directions of movement on the grid, in the order right, down, left, up.
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// A cell on the character grid, as (column, row).
+type Cell = (isize, isize);
+
+/// A search state for routing: a cell, and the index into DIRECTIONS of the
+/// direction it was entered in.
+type State = (Cell, usize);
+
+/// A cell next to a node that an edge may start or end in.
+struct Port {
+    cell: Cell,
+
+    /// The direction pointing away from the node.
+    outward: usize,
+
+    /// The point on the bounding box of the node that the edge should be
+    /// extended towards.
+    tip: Coord,
+}
+
+/// The character grid that edges are routed on.
+struct Grid {
+    cols: isize,
+    rows: isize,
+
+    /// The size of a single cell in graph coordinates.
+    cell_size: Coord,
+
+    /// Whether each cell is covered by a node.
+    blocked: Vec<bool>,
+
+    /// For each cell, bit 0 is set if a previously routed edge runs through
+    /// it horizontally, and bit 1 if one runs through it vertically.
+    used: Vec<u8>,
+}
+
+impl Grid {
+    /// Returns the index of a cell in blocked and used, if it lies within
+    /// the grid.
+    fn index(&self, (x, y): Cell) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.cols || y >= self.rows {
+            None
+        } else {
+            Some((x + y * self.cols) as usize)
+        }
+    }
+
+    /// Returns whether an edge may run through the given cell.
+    fn is_free(&self, cell: Cell) -> bool {
+        self.index(cell).is_some_and(|i| !self.blocked[i])
+    }
+
+    /// Returns the cell containing the given coordinate.
+    fn cell_of(&self, coord: Coord) -> Cell {
+        (
+            (coord.x / self.cell_size.x).floor() as isize,
+            (coord.y / self.cell_size.y).floor() as isize,
+        )
+    }
+
+    /// Returns the center of the given cell in graph coordinates.
+    fn center_of(&self, (x, y): Cell) -> Coord {
+        Coord::new(
+            (x as f64 + 0.5) * self.cell_size.x,
+            (y as f64 + 0.5) * self.cell_size.y,
+        )
+    }
+
+    /// Returns the first and last cell covered by the bounding box of a
+    /// node.
+    fn bounds_of(&self, node: &Node) -> (Cell, Cell) {
+        (
+            self.cell_of(node.coord - node.size / 2.0),
+            self.cell_of(node.coord + node.size / 2.0),
+        )
+    }
+
+    /// Returns the free cells around the sides of a node that an edge may
+    /// attach to.
+    fn ports_of(&self, node: &Node) -> Vec<Port> {
+        let (min, max) = self.bounds_of(node);
+        let a = node.coord - node.size / 2.0;
+        let b = node.coord + node.size / 2.0;
+
+        // The range of cells along a side that edges may attach to.
+        let range = |min: isize, max: isize| {
+            let center = (min + max) / 2;
+            let reach = ((max - min) as f64 * PORT_FRACTION / 2.0) as isize;
+            (center - reach).max(min)..=(center + reach).min(max)
+        };
+
+        let mut ports = vec![];
+        for x in range(min.0, max.0) {
+            let tip_x = self.center_of((x, 0)).x;
+            ports.push(Port {
+                cell: (x, max.1 + 1),
+                outward: 1,
+                tip: Coord::new(tip_x, b.y),
+            });
+            ports.push(Port {
+                cell: (x, min.1 - 1),
+                outward: 3,
+                tip: Coord::new(tip_x, a.y),
+            });
+        }
+        for y in range(min.1, max.1) {
+            let tip_y = self.center_of((0, y)).y;
+            ports.push(Port {
+                cell: (max.0 + 1, y),
+                outward: 0,
+                tip: Coord::new(b.x, tip_y),
+            });
+            ports.push(Port {
+                cell: (min.0 - 1, y),
+                outward: 2,
+                tip: Coord::new(a.x, tip_y),
+            });
+        }
+        ports.retain(|port| self.is_free(port.cell));
+        ports
+    }
+
+    /// Finds the cheapest path from any of the start ports to any of the
+    /// goal ports using A*, returning the indices of the ports used and the
+    /// cells along the way.
+    fn find_path(&self, starts: &[Port], goals: &[Port]) -> Option<(usize, usize, Vec<Cell>)> {
+        let heuristic = |(x, y): Cell| {
+            goals
+                .iter()
+                .map(|goal| ((goal.cell.0 - x).abs() + (goal.cell.1 - y).abs()) as u32)
+                .min()
+                .unwrap_or(0)
+                * STEP_COST
+        };
+
+        // States are cells combined with the direction they were entered
+        // in, so bends can be accounted for. Each state remembers the state
+        // it was reached from, or the start port it was reached from.
+        let mut best: HashMap<State, (u32, Result<State, usize>)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for (index, start) in starts.iter().enumerate() {
+            let state: State = (start.cell, start.outward);
+            if let Entry::Vacant(entry) = best.entry(state) {
+                entry.insert((0, Err(index)));
+                queue.push(Reverse((heuristic(start.cell), 0, state, None)));
+            }
+        }
+
+        while let Some(Reverse((_, cost, state, finish))) = queue.pop() {
+            // A finish marks that the goal port with that index was reached
+            // from this state; as the queue is ordered, this is the best
+            // path.
+            if let Some(goal) = finish {
+                let mut cells = vec![state.0];
+                let mut current = state;
+                loop {
+                    match best[&current].1 {
+                        Ok(previous) => {
+                            cells.push(previous.0);
+                            current = previous;
+                        }
+                        Err(start) => {
+                            cells.reverse();
+                            return Some((start, goal, cells));
+                        }
+                    }
+                }
+            }
+            if best[&state].0 < cost {
+                continue;
+            }
+
+            let (cell, direction) = state;
+            for (index, goal) in goals.iter().enumerate() {
+                if goal.cell == cell {
+                    // The edge should enter the goal node head-on.
+                    let inward = (goal.outward + 2) % 4;
+                    let bend = if direction == inward { 0 } else { BEND_COST };
+                    queue.push(Reverse((cost + bend, cost + bend, state, Some(index))));
+                }
+            }
+
+            for (next_direction, &(dx, dy)) in DIRECTIONS.iter().enumerate() {
+                if next_direction == (direction + 2) % 4 {
+                    continue;
+                }
+                let next = (cell.0 + dx, cell.1 + dy);
+                if !self.is_free(next) {
+                    continue;
+                }
+                let used = self.used[self.index(next).unwrap()];
+                let axis = 1 << (next_direction % 2);
+                let mut next_cost = cost + STEP_COST;
+                if next_direction != direction {
+                    next_cost += BEND_COST;
+                }
+                if used & axis != 0 {
+                    next_cost += OVERLAP_COST;
+                } else if used != 0 {
+                    next_cost += CROSSING_COST;
+                }
+                let next_state = (next, next_direction);
+                if let Some(&(best_cost, _)) = best.get(&next_state) {
+                    if best_cost <= next_cost {
+                        continue;
+                    }
+                }
+                best.insert(next_state, (next_cost, Ok(state)));
+                queue.push(Reverse((
+                    next_cost + heuristic(next),
+                    next_cost,
+                    next_state,
+                    None,
+                )));
+            }
+        }
+        None
+    }
+
+    /// Marks the cells along a path as used, such that later edges avoid
+    /// running along it.
+    fn mark(&mut self, cells: &[Cell]) {
+        for (i, &cell) in cells.iter().enumerate() {
+            let mut axes = 0;
+            for other in [i.checked_sub(1), Some(i + 1)].into_iter().flatten() {
+                if let Some(&other) = cells.get(other) {
+                    axes |= if other.1 == cell.1 { 1 } else { 2 };
+                }
+            }
+            if let Some(index) = self.index(cell) {
+                self.used[index] |= axes;
+            }
+        }
+    }
+}
+
+/// Routes the edges of a graph orthogonally, i.e. using only horizontal and
+/// vertical segments, on a grid of cells of the given size. This is meant
+/// for character cells, so the lines end up as clean runs of the same
+/// character. Edges go around the bounding boxes of nodes, and prefer few
+/// bends and crossings. Edges are routed in order, and later edges avoid
+/// running along earlier ones.
+///
+/// For each edge, the result is either None if no route was found (for
+/// example for self-loops), or the points along the route: the first and
+/// last points lie on the bounding boxes of the tail and head nodes, and
+/// all points in between are centers of cells.
+pub fn route_orthogonal(graph: &Graph, cell_size: Coord) -> Vec<Option<Vec<Coord>>> {
+    let mut grid = Grid {
+        cols: (graph.width / cell_size.x).ceil() as isize + 2,
+        rows: (graph.height / cell_size.y).ceil() as isize + 2,
+        cell_size,
+        blocked: vec![],
+        used: vec![],
+    };
+    grid.blocked = vec![false; (grid.cols * grid.rows) as usize];
+    grid.used = vec![0; grid.blocked.len()];
+    for node in graph.nodes.values() {
+        let (min, max) = grid.bounds_of(node);
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                if let Some(index) = grid.index((x, y)) {
+                    grid.blocked[index] = true;
+                }
+            }
+        }
+    }
+
+    let mut routes = vec![];
+    for edge in graph.edges.iter() {
+        let (Some(tail), Some(head)) = (graph.nodes.get(&edge.tail), graph.nodes.get(&edge.head))
+        else {
+            routes.push(None);
+            continue;
+        };
+        if edge.tail == edge.head {
+            routes.push(None);
+            continue;
+        }
+        let starts = grid.ports_of(tail);
+        let goals = grid.ports_of(head);
+        let Some((start, goal, cells)) = grid.find_path(&starts, &goals) else {
+            routes.push(None);
+            continue;
+        };
+        grid.mark(&cells);
+
+        let mut points = vec![starts[start].tip];
+        points.extend(cells.iter().map(|&cell| grid.center_of(cell)));
+        points.push(goals[goal].tip);
+        routes.push(Some(points));
+    }
+    routes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out a graph of 0.6 inch boxes at the given positions, with Y
+    /// pointing up like in the plain format, and routes the given edges on a
+    /// grid of 0.1 inch cells.
+    fn route(nodes: &[(&str, f64, f64)], edges: &[(&str, &str)]) -> Vec<Vec<Coord>> {
+        let mut plain = "graph 1 6 6\n".to_string();
+        for (name, x, y) in nodes {
+            plain += &format!("node {name} {x} {y} 0.6 0.6 {name} solid box black lightgrey\n");
+        }
+        for (tail, head) in edges {
+            plain += &format!("edge {tail} {head} 2 0 0 0 0 solid black\n");
+        }
+        plain += "stop\n";
+        let graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+        route_orthogonal(&graph, Coord::new(0.1, 0.1))
+            .into_iter()
+            .map(|route| route.expect("no route found"))
+            .collect()
+    }
+
+    /// Returns the number of direction changes along a route.
+    fn bends(points: &[Coord]) -> usize {
+        let directions: Vec<(bool, bool)> = points
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .filter(|d| d.x != 0.0 || d.y != 0.0)
+            .map(|d| (d.x.abs() > 1e-9, d.x > 0.0 || d.y > 0.0))
+            .collect();
+        directions
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .count()
+    }
+
+    #[test]
+    fn routes_straight_between_aligned_nodes() {
+        let routes = route(&[("a", 3.0, 5.0), ("b", 3.0, 1.0)], &[("a", "b")]);
+        assert_eq!(bends(&routes[0]), 0, "{:?}", routes[0]);
+        // From the bottom of a to the top of b, with Y pointing down.
+        assert!((routes[0][0].y - 1.3).abs() < 1e-9, "{:?}", routes[0]);
+        assert!((routes[0].last().unwrap().y - 4.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn routes_around_blocking_nodes() {
+        let routes = route(
+            &[("a", 3.0, 5.0), ("b", 3.0, 1.0), ("c", 3.0, 3.0)],
+            &[("a", "b")],
+        );
+        let points = &routes[0];
+        assert!(bends(points) >= 2, "{points:?}");
+        let blocked = |p: &Coord| (p.x - 3.0).abs() <= 0.3 && (p.y - 3.0).abs() <= 0.3;
+        assert!(!points.iter().any(blocked), "{points:?}");
+    }
+
+    #[test]
+    fn minimizes_bends() {
+        // An L-shaped route is the best that can be done here.
+        let routes = route(&[("a", 1.0, 5.0), ("b", 4.0, 1.0)], &[("a", "b")]);
+        assert_eq!(bends(&routes[0]), 1, "{:?}", routes[0]);
+    }
+
+    #[test]
+    fn crosses_earlier_edges_instead_of_running_along_them() {
+        let nodes = [
+            ("a", 1.0, 3.0),
+            ("b", 5.0, 3.0),
+            ("c", 3.0, 5.0),
+            ("d", 3.0, 1.0),
+        ];
+        let routes = route(&nodes, &[("a", "b"), ("c", "d")]);
+        let shared = routes[1]
+            .iter()
+            .filter(|p| routes[0][1..routes[0].len() - 1].contains(p))
+            .count();
+        assert_eq!(shared, 1, "{routes:?}");
+        assert_eq!(bends(&routes[1]), 0, "{:?}", routes[1]);
+
+        // Parallel edges between the same nodes do not share any cells.
+        let routes = route(&nodes[..2], &[("a", "b"), ("a", "b")]);
+        let shared = routes[1].iter().filter(|p| routes[0].contains(p)).count();
+        assert_eq!(shared, 0, "{routes:?}");
+    }
+}
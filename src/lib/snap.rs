@@ -0,0 +1,366 @@
+use crate::dot::{Coord, Graph, Node, Translatable};
+use crate::xdot::Op;
+use std::collections::HashMap;
+
+/// Number of empty columns and rows that are kept between the outlines of
+/// nodes.
+const SPACING: (isize, isize) = (2, 1);
+
+/// The box of a node on the character grid: the first and last column and
+/// row that its outline runs through.
+#[derive(Clone, Copy)]
+struct CellBox {
+    min: (isize, isize),
+    max: (isize, isize),
+}
+
+impl CellBox {
+    /// Returns the smallest box that contains the bounding box of a node,
+    /// with its outline running through the centers of cells.
+    /// Boxes are at least one cell across, such that nodes keep an
+    /// interior, and start at the first row and column at the latest, as
+    /// nodes are only ever moved right or down afterwards.
+    fn of(node: &Node, cell_size: Coord) -> CellBox {
+        let a = node.coord - node.size / 2.0;
+        let b = node.coord + node.size / 2.0;
+        let min = (
+            (a.x / cell_size.x - 0.5).floor() as isize,
+            (a.y / cell_size.y - 0.5).floor() as isize,
+        );
+        let max = (
+            (b.x / cell_size.x - 0.5).ceil() as isize,
+            (b.y / cell_size.y - 0.5).ceil() as isize,
+        );
+        let mut cells = CellBox {
+            min,
+            max: (max.0.max(min.0 + 1), max.1.max(min.1 + 1)),
+        };
+        cells.shift(-min.0.min(0), -min.1.min(0));
+        cells
+    }
+
+    /// Returns whether the given box is closer to this one than SPACING
+    /// allows.
+    fn conflicts(&self, other: &CellBox) -> bool {
+        self.min.0 <= other.max.0 + SPACING.0
+            && other.min.0 <= self.max.0 + SPACING.0
+            && self.min.1 <= other.max.1 + SPACING.1
+            && other.min.1 <= self.max.1 + SPACING.1
+    }
+
+    /// Moves the box by the given number of columns and rows.
+    fn shift(&mut self, columns: isize, rows: isize) {
+        self.min = (self.min.0 + columns, self.min.1 + rows);
+        self.max = (self.max.0 + columns, self.max.1 + rows);
+    }
+}
+
+/// How a node was moved and resized, used to move the edges attached to it
+/// along.
+struct Move {
+    /// The bounding box of the node before and after snapping.
+    from: (Coord, Coord),
+    to: (Coord, Coord),
+}
+
+impl Move {
+    /// Returns how far the center of the node moved.
+    fn offset(&self) -> Coord {
+        (self.to.0 + self.to.1 - self.from.0 - self.from.1) / 2.0
+    }
+
+    /// Maps a point on or near the outline of the node to the same place
+    /// relative to the moved node.
+    fn map(&self, point: Coord) -> Coord {
+        let axis = |p: f64, from: (f64, f64), to: (f64, f64)| {
+            if from.1 > from.0 {
+                to.0 + (p - from.0) * (to.1 - to.0) / (from.1 - from.0)
+            } else {
+                p + (to.0 + to.1 - from.0 - from.1) / 2.0
+            }
+        };
+        Coord::new(
+            axis(
+                point.x,
+                (self.from.0.x, self.from.1.x),
+                (self.to.0.x, self.to.1.x),
+            ),
+            axis(
+                point.y,
+                (self.from.0.y, self.from.1.y),
+                (self.to.0.y, self.to.1.y),
+            ),
+        )
+    }
+}
+
+/// Moves the points along an edge between two moved nodes: the first and
+/// last points stay in the same place relative to the tail and head nodes,
+/// and the points in between move by an amount interpolated between the
+/// offsets of both nodes.
+fn move_points(points: &mut [Coord], tail: &Move, head: &Move) {
+    let n = points.len();
+    for (i, point) in points.iter_mut().enumerate() {
+        *point = if i == 0 {
+            tail.map(*point)
+        } else if i == n - 1 {
+            head.map(*point)
+        } else {
+            let t = i as f64 / (n - 1) as f64;
+            *point + tail.offset() * (1.0 - t) + head.offset() * t
+        };
+    }
+}
+
+/// Snaps the nodes of a laid-out graph to a grid of cells of the given size,
+/// for graphs that are rendered at a scale where rounding would otherwise
+/// make nodes overlap or merge. Each node is grown such that its outline
+/// runs through the centers of whole cells, and nodes are then moved right
+/// or down, away from the nodes they are too close to, until SPACING cells
+/// separate them. Edges,
+/// their labels and clusters move along with their nodes, and the graph
+/// grows to fit.
+///
+/// Nodes drawn with xdot operations are only moved, as their operations
+/// cannot be resized reliably. The same goes for clusters, whose boxes are
+/// grown to contain their nodes but whose xdot operations stay as they are.
+pub fn snap_to_grid(graph: &mut Graph, cell_size: Coord) {
+    // Place nodes from top to bottom and left to right, such that each one
+    // only needs to make room for the ones placed before it.
+    let mut names: Vec<String> = graph.nodes.keys().cloned().collect();
+    let boxes: HashMap<&String, CellBox> = graph
+        .nodes
+        .iter()
+        .map(|(name, node)| (name, CellBox::of(node, cell_size)))
+        .collect();
+    names.sort_by_key(|name| (boxes[name].min.1, boxes[name].min.0, name.clone()));
+    let mut placed: Vec<(String, CellBox)> = vec![];
+    for name in names {
+        let mut cells = boxes[&name];
+        while let Some((_, other)) = placed.iter().find(|(_, other)| cells.conflicts(other)) {
+            // Move away from the other node in the direction it mostly lies
+            // in, keeping in mind that rows are about twice as high as
+            // columns are wide.
+            let dx = (cells.min.0 + cells.max.0) - (other.min.0 + other.max.0);
+            let dy = (cells.min.1 + cells.max.1) - (other.min.1 + other.max.1);
+            if dx.abs() >= dy.abs() * 2 {
+                cells.shift(other.max.0 + SPACING.0 + 1 - cells.min.0, 0);
+            } else {
+                cells.shift(0, other.max.1 + SPACING.1 + 1 - cells.min.1);
+            }
+        }
+        placed.push((name, cells));
+    }
+
+    let mut moves = HashMap::new();
+    for (name, cells) in placed {
+        let node = graph.nodes.get_mut(&name).unwrap();
+        let from = (node.coord - node.size / 2.0, node.coord + node.size / 2.0);
+        let mut a = Coord::new(
+            (cells.min.0 as f64 + 0.5) * cell_size.x,
+            (cells.min.1 as f64 + 0.5) * cell_size.y,
+        );
+        let mut b = Coord::new(
+            (cells.max.0 as f64 + 0.5) * cell_size.x,
+            (cells.max.1 as f64 + 0.5) * cell_size.y,
+        );
+        if !node.draw.is_empty() {
+            a = (a + b - node.size) / 2.0;
+            b = a + node.size;
+        }
+        node.translate((a + b) / 2.0 - node.coord);
+        node.size = b - a;
+        moves.insert(name, Move { from, to: (a, b) });
+    }
+
+    let unmoved = Move {
+        from: (Coord::new(0.0, 0.0), Coord::new(0.0, 0.0)),
+        to: (Coord::new(0.0, 0.0), Coord::new(0.0, 0.0)),
+    };
+    for edge in graph.edges.iter_mut() {
+        let tail = moves.get(&edge.tail).unwrap_or(&unmoved);
+        let head = moves.get(&edge.head).unwrap_or(&unmoved);
+        move_points(&mut edge.cpts, tail, head);
+        let middle = (tail.offset() + head.offset()) / 2.0;
+        if let Some(label) = &mut edge.label {
+            label.translate(middle);
+        }
+        for op in edge.draw.iter_mut() {
+            match op {
+                Op::Bezier { points, .. } | Op::Polyline { points } => {
+                    move_points(points, tail, head)
+                }
+                _ => op.translate(middle),
+            }
+        }
+        // Head and tail labels stay with their nodes.
+        for op in edge.head_label_draw.iter_mut() {
+            op.translate(head.offset());
+        }
+        for op in edge.tail_label_draw.iter_mut() {
+            op.translate(tail.offset());
+        }
+    }
+
+    // Grow clusters to keep a cell of room around their nodes.
+    for cluster in graph.clusters.iter_mut() {
+        let mut a = Coord::new(cluster.a.x.min(cluster.b.x), cluster.a.y.min(cluster.b.y));
+        let mut b = Coord::new(cluster.a.x.max(cluster.b.x), cluster.a.y.max(cluster.b.y));
+        for moved in cluster.nodes.iter().filter_map(|name| moves.get(name)) {
+            a.x = a.x.min(moved.to.0.x - cell_size.x);
+            a.y = a.y.min(moved.to.0.y - cell_size.y);
+            b.x = b.x.max(moved.to.1.x + cell_size.x);
+            b.y = b.y.max(moved.to.1.y + cell_size.y);
+        }
+        cluster.a = a;
+        cluster.b = b;
+    }
+
+    // Grow the graph to fit everything that moved.
+    for moved in moves.values() {
+        graph.width = graph.width.max(moved.to.1.x + cell_size.x);
+        graph.height = graph.height.max(moved.to.1.y + cell_size.y);
+    }
+    for cluster in graph.clusters.iter() {
+        graph.width = graph.width.max(cluster.b.x);
+        graph.height = graph.height.max(cluster.b.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cell size used by the tests, matching the default canvas density.
+    const CELL: Coord = Coord {
+        x: 1.0 / 16.0,
+        y: 1.0 / 8.0,
+    };
+
+    fn text_coord(ops: &[Op]) -> Coord {
+        ops.iter()
+            .find_map(|op| match op {
+                Op::Text { coord, .. } => Some(*coord),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Returns the cell box of a snapped node, rounding away the floating
+    /// point error of converting cells to coordinates and back.
+    fn snapped_box(node: &Node) -> CellBox {
+        let a = node.coord - node.size / 2.0;
+        let b = node.coord + node.size / 2.0;
+        CellBox {
+            min: (
+                (a.x / CELL.x - 0.5).round() as isize,
+                (a.y / CELL.y - 0.5).round() as isize,
+            ),
+            max: (
+                (b.x / CELL.x - 0.5).round() as isize,
+                (b.y / CELL.y - 0.5).round() as isize,
+            ),
+        }
+    }
+
+    fn snap_plain(plain: &str) -> Graph {
+        let mut graph = Graph::from_plain(&mut plain.as_bytes()).unwrap();
+        snap_to_grid(&mut graph, CELL);
+        graph
+    }
+
+    #[test]
+    fn keeps_nodes_apart() {
+        // A row of nodes that overlap, a column of nodes that overlap, and
+        // one node on top of another.
+        let graph = snap_plain(
+            "graph 1 4 3\n\
+            node a 0.5 0.5 0.75 0.5 a solid box black lightgrey\n\
+            node b 0.8 0.5 0.75 0.5 b solid box black lightgrey\n\
+            node c 1.1 0.5 0.75 0.5 c solid box black lightgrey\n\
+            node d 3 0.5 0.5 0.5 d solid ellipse black lightgrey\n\
+            node e 3 0.7 0.5 0.5 e solid ellipse black lightgrey\n\
+            node f 2 2 0.3 0.2 f solid box black lightgrey\n\
+            node g 2 2 0.3 0.2 g solid box black lightgrey\n\
+            stop\n",
+        );
+        let boxes: Vec<(&String, CellBox)> = graph
+            .nodes
+            .iter()
+            .map(|(name, node)| (name, snapped_box(node)))
+            .collect();
+        for (i, (name, cells)) in boxes.iter().enumerate() {
+            assert!(
+                cells.min.0 >= 0 && cells.min.1 >= 0,
+                "{name} is off the grid"
+            );
+            assert!(cells.max.0 > cells.min.0 && cells.max.1 > cells.min.1);
+            for (other_name, other) in boxes[i + 1..].iter() {
+                assert!(
+                    !cells.conflicts(other),
+                    "{name} and {other_name} are closer than SPACING"
+                );
+            }
+            let node = &graph.nodes[*name];
+            assert!(node.coord.x + node.size.x / 2.0 < graph.width);
+            assert!(node.coord.y + node.size.y / 2.0 < graph.height);
+        }
+    }
+
+    #[test]
+    fn keeps_nodes_on_the_grid() {
+        // Nodes that touch the top-left corner would otherwise be rounded
+        // to the cell before the first one.
+        let graph = snap_plain(
+            "graph 1 2 2\n\
+            node a 0.4 1.72 0.75 0.5 a solid box black lightgrey\n\
+            node b 1 1 0.2 0.2 b solid box black lightgrey\n\
+            stop\n",
+        );
+        let a = snapped_box(&graph.nodes["a"]);
+        assert_eq!(a.min, (0, 0));
+        let b = snapped_box(&graph.nodes["b"]);
+        assert!(!a.conflicts(&b));
+    }
+
+    #[test]
+    fn moves_head_and_tail_labels_with_their_nodes() {
+        let json = r#"{
+            "bb": "0,0,200,100",
+            "objects": [
+                {"_gvid": 0, "name": "a", "pos": "40,50", "width": "0.75", "height": "0.5"},
+                {"_gvid": 1, "name": "b", "pos": "70,50", "width": "0.75", "height": "0.5"}
+            ],
+            "edges": [{
+                "_gvid": 0, "tail": 0, "head": 1,
+                "_draw_": "c 7 -#000000 B 4 67 50 60 50 50 50 43 50",
+                "_hldraw_": "F 14 11 -Times-Roman c 7 -#000000 T 75 60 0 7 1 -h",
+                "_tldraw_": "F 14 11 -Times-Roman c 7 -#000000 T 35 60 0 7 1 -t"
+            }]
+        }"#;
+        let mut graph = Graph::from_json(&mut json.as_bytes()).unwrap();
+        let before = |graph: &Graph, name: &str| graph.nodes[name].coord;
+        let (a, b) = (before(&graph, "a"), before(&graph, "b"));
+        let head = text_coord(&graph.edges[0].head_label_draw);
+        let tail = text_coord(&graph.edges[0].tail_label_draw);
+
+        snap_to_grid(&mut graph, CELL);
+        let a_offset = graph.nodes["a"].coord - a;
+        let b_offset = graph.nodes["b"].coord - b;
+        assert!(a_offset != b_offset);
+        let moved = |from: Coord, to: Coord, offset: Coord| {
+            let d = to - from - offset;
+            d.x.abs() < 1e-9 && d.y.abs() < 1e-9
+        };
+        assert!(moved(
+            head,
+            text_coord(&graph.edges[0].head_label_draw),
+            b_offset
+        ));
+        assert!(moved(
+            tail,
+            text_coord(&graph.edges[0].tail_label_draw),
+            a_offset
+        ));
+    }
+}
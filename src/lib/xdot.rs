@@ -0,0 +1,449 @@
+use crate::dot::{Coord, Flippable, Scalable, Translatable};
+use crate::json;
+
+/// Horizontal text alignment for xdot text operations, relative to the
+/// anchor coordinate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+/// A single xdot drawing operation, as found in graphviz' _draw_, _ldraw_,
+/// _hdraw_, _tdraw_, _hldraw_ and _tldraw_ attributes. Coordinates follow
+/// the same conventions as the rest of the Graph they belong to.
+#[derive(Clone, Debug)]
+pub enum Op {
+    /// Ellipse with the given center and radii (E when filled, e when not).
+    Ellipse {
+        filled: bool,
+        center: Coord,
+        radii: Coord,
+    },
+
+    /// Closed polygon (P when filled, p when not).
+    Polygon { filled: bool, points: Vec<Coord> },
+
+    /// Open polyline (L).
+    Polyline { points: Vec<Coord> },
+
+    /// Piecewise cubic Bézier curve (B when filled, b when not). The points
+    /// follow the same conventions as Edge::cpts.
+    Bezier { filled: bool, points: Vec<Coord> },
+
+    /// Single line of text (T). The coordinate is the anchor point on the
+    /// baseline of the text, and width is the width of the text as computed
+    /// by graphviz.
+    Text {
+        coord: Coord,
+        align: Align,
+        width: f64,
+        text: String,
+    },
+
+    /// Sets the pen color for subsequent operations (c). For gradients, only
+    /// the first color stop is retained.
+    PenColor(String),
+
+    /// Sets the fill color for subsequent operations (C). For gradients,
+    /// only the first color stop is retained.
+    FillColor(String),
+
+    /// Sets the font for subsequent text operations (F).
+    Font { size: f64, name: String },
+
+    /// Sets the style for subsequent operations (S), for example dashed or
+    /// bold.
+    Style(String),
+
+    /// Sets font characteristics for subsequent text operations (t), as a
+    /// bitmask of bold, italic, underline, superscript, subscript,
+    /// strike-through and overline flags.
+    FontChars(u32),
+
+    /// Image with the given top-left corner and size (I).
+    Image {
+        coord: Coord,
+        size: Coord,
+        name: String,
+    },
+}
+
+impl Scalable for Op {
+    fn scale(&mut self, scale: f64) {
+        match self {
+            Op::Ellipse { center, radii, .. } => {
+                center.scale(scale);
+                radii.scale(scale);
+            }
+            Op::Polygon { points, .. } | Op::Polyline { points } | Op::Bezier { points, .. } => {
+                for point in points.iter_mut() {
+                    point.scale(scale);
+                }
+            }
+            Op::Text { coord, width, .. } => {
+                coord.scale(scale);
+                *width *= scale;
+            }
+            Op::Font { size, .. } => {
+                *size *= scale;
+            }
+            Op::Image { coord, size, .. } => {
+                coord.scale(scale);
+                size.scale(scale);
+            }
+            Op::PenColor(_) | Op::FillColor(_) | Op::Style(_) | Op::FontChars(_) => (),
+        }
+    }
+}
+
+impl Flippable for Op {
+    fn flip_y(&mut self, height: f64) {
+        match self {
+            Op::Ellipse { center, .. } => {
+                center.flip_y(height);
+            }
+            Op::Polygon { points, .. } | Op::Polyline { points } | Op::Bezier { points, .. } => {
+                for point in points.iter_mut() {
+                    point.flip_y(height);
+                }
+            }
+            Op::Text { coord, .. } => {
+                coord.flip_y(height);
+            }
+            Op::Image { coord, size, .. } => {
+                // xdot specifies the bottom-left corner of images.
+                coord.flip_y(height);
+                coord.y -= size.y;
+            }
+            Op::PenColor(_)
+            | Op::FillColor(_)
+            | Op::Font { .. }
+            | Op::Style(_)
+            | Op::FontChars(_) => (),
+        }
+    }
+}
+
+impl Translatable for Op {
+    fn translate(&mut self, offset: Coord) {
+        match self {
+            Op::Ellipse { center, .. } => {
+                center.translate(offset);
+            }
+            Op::Polygon { points, .. } | Op::Polyline { points } | Op::Bezier { points, .. } => {
+                for point in points.iter_mut() {
+                    point.translate(offset);
+                }
+            }
+            Op::Text { coord, .. } | Op::Image { coord, .. } => {
+                coord.translate(offset);
+            }
+            Op::PenColor(_)
+            | Op::FillColor(_)
+            | Op::Font { .. }
+            | Op::Style(_)
+            | Op::FontChars(_) => (),
+        }
+    }
+}
+
+impl Op {
+    /// Parses a coordinate pair given as a JSON array.
+    fn json_coord(value: Option<&json::Value>) -> Result<Coord, String> {
+        match value.and_then(json::Value::as_array) {
+            Some([x, y, ..]) => match (x.as_f64(), y.as_f64()) {
+                (Some(x), Some(y)) => Ok(Coord::new(x, y)),
+                _ => Err("invalid coordinate in xdot operation".to_string()),
+            },
+            _ => Err("missing coordinate in xdot operation".to_string()),
+        }
+    }
+
+    /// Parses a list of coordinate pairs given as a JSON array of arrays.
+    fn json_points(value: &json::Value) -> Result<Vec<Coord>, String> {
+        value
+            .get("points")
+            .and_then(json::Value::as_array)
+            .ok_or_else(|| "missing points in xdot operation".to_string())?
+            .iter()
+            .map(|point| Op::json_coord(Some(point)))
+            .collect()
+    }
+
+    /// Parses a number in a JSON xdot operation.
+    fn json_f64(value: &json::Value, key: &str) -> Result<f64, String> {
+        value
+            .get(key)
+            .and_then(json::Value::as_f64)
+            .ok_or_else(|| format!("missing {key} in xdot operation"))
+    }
+
+    /// Parses a string in a JSON xdot operation.
+    fn json_string(value: &json::Value, key: &str) -> Result<String, String> {
+        value
+            .get(key)
+            .and_then(json::Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| format!("missing {key} in xdot operation"))
+    }
+
+    /// Parses a color in a JSON xdot operation. For gradients, the color of
+    /// the first stop is used.
+    fn json_color(value: &json::Value) -> Result<String, String> {
+        if let Some(color) = value.get("color").and_then(json::Value::as_str) {
+            return Ok(color.to_string());
+        }
+        value
+            .get("stops")
+            .and_then(json::Value::as_array)
+            .and_then(|stops| stops.first())
+            .and_then(|stop| stop.get("color"))
+            .and_then(json::Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| "missing color in xdot operation".to_string())
+    }
+
+    /// Parses a single operation from graphviz' JSON output format, where
+    /// each operation is an object with an "op" member holding the xdot
+    /// operation character.
+    pub(crate) fn from_json(value: &json::Value) -> Result<Op, String> {
+        let op = value
+            .get("op")
+            .and_then(json::Value::as_str)
+            .ok_or_else(|| "missing op in xdot operation".to_string())?;
+        Ok(match op {
+            "E" | "e" => match value.get("rect").and_then(json::Value::as_array) {
+                Some([x, y, w, h]) => match (x.as_f64(), y.as_f64(), w.as_f64(), h.as_f64()) {
+                    (Some(x), Some(y), Some(w), Some(h)) => Op::Ellipse {
+                        filled: op == "E",
+                        center: Coord::new(x, y),
+                        radii: Coord::new(w, h),
+                    },
+                    _ => return Err("invalid rect in xdot ellipse operation".to_string()),
+                },
+                _ => return Err("missing rect in xdot ellipse operation".to_string()),
+            },
+            "P" | "p" => Op::Polygon {
+                filled: op == "P",
+                points: Op::json_points(value)?,
+            },
+            "L" => Op::Polyline {
+                points: Op::json_points(value)?,
+            },
+            "B" | "b" => Op::Bezier {
+                filled: op == "B",
+                points: Op::json_points(value)?,
+            },
+            "T" => Op::Text {
+                coord: Op::json_coord(value.get("pt"))?,
+                align: match value.get("align").and_then(json::Value::as_str) {
+                    Some("l") => Align::Left,
+                    Some("r") => Align::Right,
+                    _ => Align::Center,
+                },
+                width: Op::json_f64(value, "width")?,
+                text: Op::json_string(value, "text")?,
+            },
+            "c" => Op::PenColor(Op::json_color(value)?),
+            "C" => Op::FillColor(Op::json_color(value)?),
+            "F" => Op::Font {
+                size: Op::json_f64(value, "size")?,
+                name: Op::json_string(value, "face")?,
+            },
+            "S" => Op::Style(Op::json_string(value, "style")?),
+            "t" => Op::FontChars(Op::json_f64(value, "fontchar")? as u32),
+            "I" => Op::Image {
+                coord: Op::json_coord(value.get("pos"))?,
+                size: Op::json_coord(value.get("size"))?,
+                name: Op::json_string(value, "name")?,
+            },
+            unknown => return Err(format!("unknown xdot operation {unknown}")),
+        })
+    }
+}
+
+/// Parses the textual xdot representation of a list of drawing operations,
+/// as found in the _draw_ and related attributes of graphviz' xdot output
+/// format.
+pub fn parse(text: &str) -> Result<Vec<Op>, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut ops = vec![];
+    while let Some(op) = parser.word() {
+        ops.push(match op {
+            "E" | "e" => Op::Ellipse {
+                filled: op == "E",
+                center: parser.coord()?,
+                radii: parser.coord()?,
+            },
+            "P" | "p" => Op::Polygon {
+                filled: op == "P",
+                points: parser.points()?,
+            },
+            "L" => Op::Polyline {
+                points: parser.points()?,
+            },
+            "B" | "b" => Op::Bezier {
+                filled: op == "B",
+                points: parser.points()?,
+            },
+            "T" => Op::Text {
+                coord: parser.coord()?,
+                align: match parser.float()? {
+                    j if j < 0.0 => Align::Left,
+                    j if j > 0.0 => Align::Right,
+                    _ => Align::Center,
+                },
+                width: parser.float()?,
+                text: parser.string()?,
+            },
+            "c" => Op::PenColor(parser.string()?),
+            "C" => Op::FillColor(parser.string()?),
+            "F" => Op::Font {
+                size: parser.float()?,
+                name: parser.string()?,
+            },
+            "S" => Op::Style(parser.string()?),
+            "t" => Op::FontChars(parser.integer()? as u32),
+            "I" => Op::Image {
+                coord: parser.coord()?,
+                size: parser.coord()?,
+                name: parser.string()?,
+            },
+            unknown => {
+                return Err(format!(
+                    "unknown xdot operation {unknown} at byte {}",
+                    parser.pos
+                ))
+            }
+        });
+    }
+    Ok(ops)
+}
+
+/// Tokenizer state for parse().
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the next whitespace-delimited word, if any.
+    fn word(&mut self) -> Option<&'a str> {
+        let rest = &self.text[self.pos..];
+        let start = rest.len() - rest.trim_start().len();
+        let len = rest[start..]
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len() - start);
+        self.pos += start + len;
+        Some(&rest[start..start + len]).filter(|word| !word.is_empty())
+    }
+
+    /// Parses a floating-point word, throwing a reasonable error message on
+    /// failure.
+    fn float(&mut self) -> Result<f64, String> {
+        let pos = self.pos;
+        let word = self
+            .word()
+            .ok_or_else(|| "unexpected end of xdot operations".to_string())?;
+        word.parse().map_err(|e| {
+            format!("failed to parse xdot number at byte {pos} as float ('{word}'): {e}")
+        })
+    }
+
+    /// Parses an integer word, throwing a reasonable error message on
+    /// failure.
+    fn integer(&mut self) -> Result<usize, String> {
+        let pos = self.pos;
+        let word = self
+            .word()
+            .ok_or_else(|| "unexpected end of xdot operations".to_string())?;
+        word.parse().map_err(|e| {
+            format!("failed to parse xdot number at byte {pos} as integer ('{word}'): {e}")
+        })
+    }
+
+    /// Parses a pair of floating-point words as a coordinate.
+    fn coord(&mut self) -> Result<Coord, String> {
+        Ok(Coord::new(self.float()?, self.float()?))
+    }
+
+    /// Parses a point count followed by that many coordinates.
+    fn points(&mut self) -> Result<Vec<Coord>, String> {
+        let count = self.integer()?;
+        (0..count).map(|_| self.coord()).collect()
+    }
+
+    /// Parses a string, given as a byte count followed by whitespace, a dash,
+    /// and then exactly that many bytes.
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.integer()?;
+        let rest = &self.text[self.pos..];
+        let start = rest.len() - rest.trim_start().len();
+        if !rest[start..].starts_with('-') {
+            return Err(format!(
+                "expected '-' before xdot string at byte {}",
+                self.pos + start
+            ));
+        }
+        let start = self.pos + start + 1;
+        let invalid = || format!("invalid length for xdot string at byte {start}");
+        let end = start.checked_add(len).ok_or_else(invalid)?;
+        let string = self.text.get(start..end).ok_or_else(invalid)?;
+        self.pos = end;
+        Ok(string.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_shapes() {
+        let ops = parse("e 27 18 27 18 P 3 0 0 1 0 1 1 L 2 0 0 3 4 b 4 0 0 1 1 2 2 3 3").unwrap();
+        assert_eq!(ops.len(), 4);
+        assert!(matches!(
+            ops[0],
+            Op::Ellipse { filled: false, center, radii }
+                if center == Coord::new(27.0, 18.0) && radii == Coord::new(27.0, 18.0)
+        ));
+        assert!(matches!(&ops[1], Op::Polygon { filled: true, points } if points.len() == 3));
+        assert!(matches!(&ops[2], Op::Polyline { points } if points[1] == Coord::new(3.0, 4.0)));
+        assert!(matches!(&ops[3], Op::Bezier { filled: false, points } if points.len() == 4));
+    }
+
+    #[test]
+    fn parses_strings_by_byte_count() {
+        let ops =
+            parse("F 14 11 -Times-Roman c 7 -#ff0000 T 27 18 -1 30 6 -a b ü S 6 -dashed").unwrap();
+        assert!(
+            matches!(&ops[0], Op::Font { size, name } if *size == 14.0 && name == "Times-Roman")
+        );
+        assert!(matches!(&ops[1], Op::PenColor(color) if color == "#ff0000"));
+        assert!(matches!(
+            &ops[2],
+            Op::Text { align: Align::Left, width, text, .. } if *width == 30.0 && text == "a b ü"
+        ));
+        assert!(matches!(&ops[3], Op::Style(style) if style == "dashed"));
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert!(parse("e 1 2 3").is_err());
+        assert!(parse("X 1 2").is_err());
+        assert!(parse("c 3 red").is_err());
+        assert!(parse("c 10 -red").is_err());
+        assert!(parse("L 2 0 0").is_err());
+        assert!(parse("T 0 0 0 1 x -a").is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_string_length() {
+        let text = format!("c {} -red", usize::MAX);
+        let error = parse(&text).unwrap_err();
+        assert!(error.starts_with("invalid length"), "{error}");
+    }
+}